@@ -0,0 +1,3 @@
+-- Instances removed once they answered their first proxied request.
+
+ALTER TABLE instance_info ADD COLUMN oneshot BOOLEAN NOT NULL DEFAULT 0;
@@ -0,0 +1,3 @@
+-- Tokens restricted to a single instance, issued at start.
+
+ALTER TABLE instance_info ADD COLUMN instance_token TEXT;
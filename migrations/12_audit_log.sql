@@ -0,0 +1,12 @@
+-- Append-only record of the actions on instances and users.
+
+CREATE TABLE IF NOT EXISTS audit_log (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    actor TEXT NOT NULL,
+    action TEXT NOT NULL,
+    target TEXT,
+    created_at INTEGER NOT NULL,
+    source_ip TEXT
+);
+
+CREATE INDEX IF NOT EXISTS audit_log_created_at ON audit_log (created_at);
@@ -0,0 +1,3 @@
+-- RPC endpoint the instances are forked from.
+
+ALTER TABLE instance_info ADD COLUMN fork_rpc_url TEXT;
@@ -0,0 +1,6 @@
+-- Names of the removed instances kept reserved until they expire.
+
+CREATE TABLE IF NOT EXISTS instance_tombstone (
+    instance_name TEXT PRIMARY KEY NOT NULL,
+    expires_at INTEGER NOT NULL
+);
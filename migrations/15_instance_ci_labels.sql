@@ -0,0 +1,4 @@
+-- CI system and run the instances were started from.
+
+ALTER TABLE instance_info ADD COLUMN ci_system TEXT;
+ALTER TABLE instance_info ADD COLUMN ci_run_id TEXT;
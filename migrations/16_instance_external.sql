@@ -0,0 +1,3 @@
+-- Host of the externally managed Katanas, reached without docker.
+
+ALTER TABLE instance_info ADD COLUMN external_host TEXT;
@@ -0,0 +1,33 @@
+-- Creation time of the instances set by the database. SQLite can't change
+-- the default of a column, so the table is rebuilt.
+
+CREATE TABLE instance_info_new (
+       container_id TEXT NOT NULL,
+       instance_name TEXT NOT NULL,
+       api_key TEXT NOT NULL,
+       proxied_port INT NOT NULL,
+       created_at INTEGER NOT NULL DEFAULT (CAST(strftime('%s', 'now') AS INTEGER)),
+       last_seen INTEGER,
+       image TEXT NOT NULL DEFAULT '',
+       launch_args TEXT NOT NULL DEFAULT '[]',
+       metrics_port INT,
+       state TEXT NOT NULL DEFAULT 'running',
+       reservation_token TEXT,
+       tags TEXT NOT NULL DEFAULT '{}',
+       startup_log TEXT,
+       persist_dir TEXT,
+       oneshot BOOLEAN NOT NULL DEFAULT 0,
+       instance_token TEXT,
+       fork_rpc_url TEXT,
+       ci_system TEXT,
+       ci_run_id TEXT,
+       external_host TEXT,
+
+       PRIMARY KEY (`instance_name`)
+);
+
+INSERT INTO instance_info_new (container_id, instance_name, api_key, proxied_port, created_at, last_seen, image, launch_args, metrics_port, state, reservation_token, tags, startup_log, persist_dir, oneshot, instance_token, fork_rpc_url, ci_system, ci_run_id, external_host)
+SELECT container_id, instance_name, api_key, proxied_port, created_at, last_seen, image, launch_args, metrics_port, state, reservation_token, tags, startup_log, persist_dir, oneshot, instance_token, fork_rpc_url, ci_system, ci_run_id, external_host FROM instance_info;
+
+DROP TABLE instance_info;
+ALTER TABLE instance_info_new RENAME TO instance_info;
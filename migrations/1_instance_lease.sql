@@ -0,0 +1,4 @@
+-- Track instance creation and latest heartbeat for the reaper.
+
+ALTER TABLE instance_info ADD COLUMN created_at INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE instance_info ADD COLUMN last_seen INTEGER;
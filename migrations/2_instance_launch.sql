@@ -0,0 +1,4 @@
+-- Persist the image and the command used to launch an instance.
+
+ALTER TABLE instance_info ADD COLUMN image TEXT NOT NULL DEFAULT '';
+ALTER TABLE instance_info ADD COLUMN launch_args TEXT NOT NULL DEFAULT '[]';
@@ -0,0 +1,3 @@
+-- Port of the Katana metrics endpoint, when enabled.
+
+ALTER TABLE instance_info ADD COLUMN metrics_port INT;
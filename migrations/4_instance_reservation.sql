@@ -0,0 +1,4 @@
+-- Instance lifecycle state, and token of the pending name reservations.
+
+ALTER TABLE instance_info ADD COLUMN state TEXT NOT NULL DEFAULT 'running';
+ALTER TABLE instance_info ADD COLUMN reservation_token TEXT;
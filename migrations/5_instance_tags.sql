@@ -0,0 +1,3 @@
+-- User defined tags of the instances, as a JSON object.
+
+ALTER TABLE instance_info ADD COLUMN tags TEXT NOT NULL DEFAULT '{}';
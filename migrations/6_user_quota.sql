@@ -0,0 +1,4 @@
+-- User quota of running instances, and admin flag.
+
+ALTER TABLE user_info ADD COLUMN max_instances INT;
+ALTER TABLE user_info ADD COLUMN is_admin BOOLEAN NOT NULL DEFAULT 0;
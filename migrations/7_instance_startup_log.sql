@@ -0,0 +1,3 @@
+-- First log lines of the instances, captured once ready.
+
+ALTER TABLE instance_info ADD COLUMN startup_log TEXT;
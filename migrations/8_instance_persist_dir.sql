@@ -0,0 +1,3 @@
+-- Host directory persisting the database of the instances.
+
+ALTER TABLE instance_info ADD COLUMN persist_dir TEXT;
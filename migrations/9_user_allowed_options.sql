@@ -0,0 +1,3 @@
+-- Start options the users can set, as a JSON array, `null` allowing all of them.
+
+ALTER TABLE user_info ADD COLUMN allowed_options TEXT NOT NULL DEFAULT 'null';
@@ -0,0 +1,98 @@
+//! Circuit breaker, failing fast while a dependency is unhealthy
+//! instead of piling up requests bound to time out.
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tracing::{info, warn};
+
+#[derive(Debug, Default)]
+struct BreakerState {
+    /// Number of consecutive failures.
+    failures: u32,
+    /// End of the cooldown while the breaker is open.
+    open_until: Option<Instant>,
+}
+
+#[derive(Debug, Clone)]
+pub struct CircuitBreaker {
+    /// Consecutive failures opening the breaker.
+    threshold: u32,
+    /// Time the calls are short-circuited once opened.
+    cooldown: Duration,
+    state: Arc<Mutex<BreakerState>>,
+}
+
+impl CircuitBreaker {
+    pub fn new(threshold: u32, cooldown: Duration) -> Self {
+        Self {
+            threshold,
+            cooldown,
+            state: Default::default(),
+        }
+    }
+
+    /// Checks if a call can go through, returning the remaining cooldown
+    /// otherwise. Once the cooldown is over, a single call is let through
+    /// to probe the dependency, the others being short-circuited until
+    /// its outcome is recorded.
+    pub fn check(&self) -> Result<(), Duration> {
+        let mut state = self.state.lock().unwrap();
+        let now = Instant::now();
+
+        match state.open_until {
+            Some(until) if now < until => Err(until - now),
+            Some(_) => {
+                state.open_until = Some(now + self.cooldown);
+                Ok(())
+            }
+            None => Ok(()),
+        }
+    }
+
+    /// Records the outcome of a call let through.
+    pub fn record(&self, success: bool) {
+        let mut state = self.state.lock().unwrap();
+
+        if success {
+            if state.open_until.take().is_some() {
+                info!("circuit breaker closed");
+            }
+            state.failures = 0;
+            return;
+        }
+
+        state.failures += 1;
+        if state.failures >= self.threshold {
+            if state.open_until.is_none() {
+                warn!(
+                    "circuit breaker opened after {} consecutive failures",
+                    state.failures
+                );
+            }
+            state.open_until = Some(Instant::now() + self.cooldown);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn breaker_opens_after_the_threshold_and_closes_after_a_successful_probe() {
+        let breaker = CircuitBreaker::new(2, Duration::from_millis(50));
+
+        breaker.record(false);
+        assert!(breaker.check().is_ok());
+        breaker.record(false);
+        assert!(breaker.check().is_err());
+
+        std::thread::sleep(Duration::from_millis(60));
+        // A single probe goes through once the cooldown is over.
+        assert!(breaker.check().is_ok());
+        assert!(breaker.check().is_err());
+
+        breaker.record(true);
+        assert!(breaker.check().is_ok());
+        assert!(breaker.check().is_ok());
+    }
+}
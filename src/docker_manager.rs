@@ -1,10 +1,30 @@
 //! Docker abstraction to create, start and stop containers.
-use futures_util::stream::StreamExt;
+use base64::Engine;
+use futures_util::stream::{Stream, StreamExt};
+use hyper::client::HttpConnector;
+use hyper::{header, Body, Request};
+use hyper_openssl::HttpsConnector;
+use openssl::ssl::{SslConnector, SslFiletype, SslMethod};
+use serde::{ser::SerializeStruct, Serialize, Serializer};
+use shiplift::rep::{ContainerCreateInfo, NetworkSettings};
 use shiplift::tty::TtyChunk;
 use shiplift::{
-    errors::Error as ShipliftError, ContainerOptions, Docker, LogsOptions, RmContainerOptions,
+    errors::Error as ShipliftError, ContainerFilter, ContainerListOptions, ContainerOptions,
+    Docker, LogsOptions, PullOptions, RegistryAuth, RmContainerOptions,
 };
-use tracing::trace;
+use std::collections::HashMap;
+use std::env;
+use std::fmt;
+use std::future::Future;
+use std::net::IpAddr;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::Semaphore;
+use tracing::{info, trace, warn};
+
+use crate::breaker::CircuitBreaker;
+use crate::config::redact_launch_args;
 
 /// Errors for docker operations.
 #[derive(Debug, thiserror::Error)]
@@ -13,6 +33,20 @@ pub enum DockerError {
     Generic(String),
     #[error("Shiplift error: {0}")]
     Shiplift(ShipliftError),
+    #[error("Docker is unavailable, retry in {}s", .0.as_secs() + 1)]
+    Unavailable(Duration),
+}
+
+impl DockerError {
+    /// Whether the daemon failed, as opposed to rejecting the call,
+    /// like for a port already allocated.
+    fn is_daemon_failure(&self) -> bool {
+        match self {
+            Self::Shiplift(ShipliftError::Fault { code, .. }) => code.is_server_error(),
+            Self::Unavailable(_) => false,
+            _ => true,
+        }
+    }
 }
 
 impl From<ShipliftError> for DockerError {
@@ -21,20 +55,443 @@ impl From<ShipliftError> for DockerError {
     }
 }
 
+/// Docker restart policy of the Katana containers.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum RestartPolicy {
+    #[default]
+    No,
+    OnFailure,
+    UnlessStopped,
+}
+
+impl RestartPolicy {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::No => "no",
+            Self::OnFailure => "on-failure",
+            Self::UnlessStopped => "unless-stopped",
+        }
+    }
+}
+
+impl FromStr for RestartPolicy {
+    type Err = DockerError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "no" => Ok(Self::No),
+            "on-failure" => Ok(Self::OnFailure),
+            "unless-stopped" => Ok(Self::UnlessStopped),
+            _ => Err(DockerError::Generic(format!("invalid restart policy {s}"))),
+        }
+    }
+}
+
+/// How the proxifier reaches the Katana instances.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Upstream {
+    /// Through the instance port, published on the host.
+    #[default]
+    HostPort,
+    /// Through the container IP, without publishing any port.
+    ContainerIp,
+}
+
+impl FromStr for Upstream {
+    type Err = DockerError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "host_port" => Ok(Self::HostPort),
+            "container_ip" => Ok(Self::ContainerIp),
+            _ => Err(DockerError::Generic(format!("invalid upstream {s}"))),
+        }
+    }
+}
+
+/// Credentials to pull the image from a private registry.
+#[derive(Clone)]
+pub struct RegistryCredentials {
+    pub username: String,
+    pub password: String,
+    pub server_address: Option<String>,
+}
+
+impl RegistryCredentials {
+    /// Decodes the base64 `username:password` form used in
+    /// the `auth` field of docker `config.json`.
+    pub fn from_base64(auth: &str) -> Result<Self, DockerError> {
+        let decoded = base64::engine::general_purpose::STANDARD
+            .decode(auth.trim())
+            .ok()
+            .and_then(|d| String::from_utf8(d).ok())
+            .ok_or(DockerError::Generic(
+                "invalid base64 registry auth".to_string(),
+            ))?;
+
+        let (username, password) = decoded.split_once(':').ok_or(DockerError::Generic(
+            "registry auth must be username:password".to_string(),
+        ))?;
+
+        Ok(Self {
+            username: username.to_string(),
+            password: password.to_string(),
+            server_address: None,
+        })
+    }
+
+    fn to_registry_auth(&self) -> RegistryAuth {
+        let mut builder = RegistryAuth::builder();
+        builder.username(&self.username).password(&self.password);
+
+        if let Some(server_address) = &self.server_address {
+            builder.server_address(server_address);
+        }
+
+        builder.build()
+    }
+}
+
+impl fmt::Debug for RegistryCredentials {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RegistryCredentials")
+            .field("username", &self.username)
+            .field("password", &"<redacted>")
+            .field("server_address", &self.server_address)
+            .finish()
+    }
+}
+
+impl Serialize for RegistryCredentials {
+    fn serialize<S: Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+        let mut state = s.serialize_struct("RegistryCredentials", 3)?;
+        state.serialize_field("username", &self.username)?;
+        state.serialize_field("password", "<redacted>")?;
+        state.serialize_field("server_address", &self.server_address)?;
+        state.end()
+    }
+}
+
+/// Client of the docker daemon for the calls shiplift can't make, over
+/// the same transport as shiplift: the unix socket, or TCP with TLS
+/// when `DOCKER_CERT_PATH` is set.
+#[derive(Clone)]
+enum DaemonClient {
+    Unix {
+        client: hyper::Client<hyperlocal::UnixConnector>,
+        path: String,
+    },
+    Tcp {
+        client: hyper::Client<HttpConnector>,
+        host: String,
+    },
+    Tls {
+        client: hyper::Client<HttpsConnector<HttpConnector>>,
+        host: String,
+    },
+}
+
+impl DaemonClient {
+    /// Client of the daemon at `DOCKER_HOST`, the local socket if not set.
+    fn from_env() -> Self {
+        let host = env::var("DOCKER_HOST").unwrap_or(DEFAULT_DOCKER_HOST.to_string());
+        let cert_path = env::var("DOCKER_CERT_PATH").ok();
+        let verify = env::var("DOCKER_TLS_VERIFY").is_ok();
+        Self::new(&host, cert_path.as_deref(), verify)
+    }
+
+    fn new(host: &str, cert_path: Option<&str>, verify: bool) -> Self {
+        if let Some(path) = host.strip_prefix("unix://") {
+            return Self::Unix {
+                client: hyper::Client::builder().build(hyperlocal::UnixConnector),
+                path: path.to_string(),
+            };
+        }
+
+        let cert_path = match cert_path {
+            Some(cert_path) => cert_path,
+            None => {
+                return Self::Tcp {
+                    client: hyper::Client::new(),
+                    host: host.replacen("tcp://", "http://", 1),
+                }
+            }
+        };
+
+        let mut ssl = SslConnector::builder(SslMethod::tls()).expect("can't initialize TLS");
+        ssl.set_certificate_file(format!("{cert_path}/cert.pem"), SslFiletype::PEM)
+            .expect("invalid docker TLS certificate");
+        ssl.set_private_key_file(format!("{cert_path}/key.pem"), SslFiletype::PEM)
+            .expect("invalid docker TLS key");
+        if verify {
+            ssl.set_ca_file(format!("{cert_path}/ca.pem"))
+                .expect("invalid docker TLS CA");
+        }
+
+        let mut http = HttpConnector::new();
+        http.enforce_http(false);
+
+        Self::Tls {
+            client: hyper::Client::builder()
+                .build(HttpsConnector::with_connector(http, ssl).expect("can't initialize TLS")),
+            host: host.replacen("tcp://", "https://", 1),
+        }
+    }
+
+    /// Posts a JSON body to the given API path.
+    async fn post_json(
+        &self,
+        path: &str,
+        body: String,
+    ) -> Result<hyper::Response<Body>, DockerError> {
+        let request = |uri: hyper::Uri| {
+            Request::post(uri)
+                .header(header::CONTENT_TYPE, "application/json")
+                .body(Body::from(body))
+                .map_err(|e| DockerError::Generic(e.to_string()))
+        };
+        let tcp_uri = |host: &str| {
+            format!("{host}{path}")
+                .parse()
+                .map_err(|e: hyper::http::uri::InvalidUri| DockerError::Generic(e.to_string()))
+        };
+
+        match self {
+            Self::Unix {
+                client,
+                path: socket,
+            } => {
+                client
+                    .request(request(hyperlocal::Uri::new(socket, path).into())?)
+                    .await
+            }
+            Self::Tcp { client, host } => client.request(request(tcp_uri(host)?)?).await,
+            Self::Tls { client, host } => client.request(request(tcp_uri(host)?)?).await,
+        }
+        .map_err(|e| DockerError::Generic(format!("can't reach docker: {e}")))
+    }
+}
+
 #[derive(Clone)]
 pub struct DockerManager {
     docker: Docker,
+    /// Sends the container creations shiplift can't express.
+    daemon: DaemonClient,
     image: String,
+    restart_policy: RestartPolicy,
+    registry_auth: Option<RegistryCredentials>,
+    upstream: Upstream,
+    publish_interface: String,
+    /// Alternative images, keyed by alias.
+    images: HashMap<String, String>,
+    /// Breaker of the container creations and starts.
+    breaker: Option<CircuitBreaker>,
+    /// Runs the containers with a read-only root filesystem.
+    readonly_rootfs: bool,
+    /// Bounds the concurrent container creations, starts and removals.
+    limit: Option<Arc<Semaphore>>,
+    /// IP address of the containers, inspected once per start.
+    ips: Arc<Mutex<HashMap<String, IpAddr>>>,
+}
+
+/// Validates an image reference, `[registry/]path[:tag][@sha256:<hex>]`,
+/// returning it with an explicit `:latest` tag when it has neither tag
+/// nor digest. Image IDs are returned as is.
+pub fn normalize_image_reference(image: &str) -> Option<String> {
+    let id = image.strip_prefix("sha256:").unwrap_or(image);
+    if matches!(id.len(), 12 | 64) && id.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Some(image.to_string());
+    }
+
+    let (name, digest) = match image.split_once('@') {
+        Some((name, digest)) => (name, Some(digest)),
+        None => (image, None),
+    };
+
+    if let Some(digest) = digest {
+        let hex = digest.strip_prefix("sha256:")?;
+        if hex.len() != 64 || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+            return None;
+        }
+    }
+
+    // The tag can only follow the last component, the registry
+    // host being allowed to have a port.
+    let last_slash = name.rfind('/').map_or(0, |i| i + 1);
+    let (path, tag) = match name[last_slash..].rfind(':') {
+        Some(i) => (&name[..last_slash + i], Some(&name[last_slash + i + 1..])),
+        None => (name, None),
+    };
+
+    if let Some(tag) = tag {
+        let valid_tag = tag.len() <= 128
+            && tag.starts_with(|c: char| c.is_ascii_alphanumeric() || c == '_')
+            && tag
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '.' | '-'));
+        if !valid_tag {
+            return None;
+        }
+    }
+
+    let mut components: Vec<&str> = path.split('/').collect();
+    if components.len() > 1 && (components[0].contains(['.', ':']) || components[0] == "localhost")
+    {
+        let registry = components.remove(0);
+        let valid_registry = registry
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '-' | ':'));
+        if !valid_registry {
+            return None;
+        }
+    }
+
+    let valid_path = components.iter().all(|c| {
+        c.starts_with(|c: char| c.is_ascii_lowercase() || c.is_ascii_digit())
+            && c.ends_with(|c: char| c.is_ascii_lowercase() || c.is_ascii_digit())
+            && c.chars().all(|c| {
+                c.is_ascii_lowercase() || c.is_ascii_digit() || matches!(c, '.' | '_' | '-')
+            })
+    });
+    if !valid_path {
+        return None;
+    }
+
+    match (tag, digest) {
+        (None, None) => Some(format!("{name}:latest")),
+        _ => Some(image.to_string()),
+    }
 }
 
-#[derive(Debug, Default)]
+/// IP address of a container, on the default bridge or
+/// on the first network it's attached to.
+fn container_ip(settings: &NetworkSettings) -> Option<IpAddr> {
+    std::iter::once(&settings.ip_address)
+        .chain(settings.networks.values().map(|n| &n.ip_address))
+        .find_map(|ip| ip.parse().ok())
+}
+
+/// Binds the published ports of the serialized container
+/// options to the given host interface.
+fn bind_ports(json: &mut serde_json::Value, interface: &str) {
+    if let Some(bindings) = json
+        .pointer_mut("/HostConfig/PortBindings")
+        .and_then(|b| b.as_object_mut())
+    {
+        for binding in bindings
+            .values_mut()
+            .filter_map(|b| b.as_array_mut())
+            .flatten()
+        {
+            binding["HostIp"] = interface.into();
+        }
+    }
+}
+
+/// Makes the root filesystem of the serialized container options
+/// read-only, with a tmpfs mounted on `/tmp` for Katana to write.
+fn set_readonly_rootfs(json: &mut serde_json::Value) {
+    json["HostConfig"]["ReadonlyRootfs"] = true.into();
+    json["HostConfig"]["Tmpfs"] = serde_json::json!({ "/tmp": "rw,noexec,nosuid" });
+}
+
+/// Default host interface of the published ports, only
+/// reachable by the proxifier.
+pub const DEFAULT_PUBLISH_INTERFACE: &str = "127.0.0.1";
+/// Docker daemon address when `DOCKER_HOST` is not set, as for shiplift.
+const DEFAULT_DOCKER_HOST: &str = "unix:///var/run/docker.sock";
+const CREATE_CONTAINER_PATH: &str = "/containers/create";
+
+/// Label set on every container created by the proxifier.
+const MANAGED_LABEL: &str = "katana-ci.managed";
+
+/// Label of the containers created for the warm pool, set to their port.
+const POOL_LABEL: &str = "katana-ci.pool";
+
+/// Managed container, as listed by docker.
+#[derive(Debug, Clone)]
+pub struct ManagedContainer {
+    pub id: String,
+    /// Unix timestamp of the container creation.
+    pub created: i64,
+    pub running: bool,
+    /// Port of a container created for the warm pool.
+    pub pool_port: Option<u16>,
+}
+
+/// State of a container, as inspected.
+#[derive(Debug, Clone, Serialize)]
+pub struct ContainerState {
+    /// Docker status, like `running` or `exited`.
+    pub status: String,
+    pub running: bool,
+    /// Exit code of the last run, `0` if it never exited.
+    pub exit_code: u64,
+    /// RFC 3339 date of the last start.
+    pub started_at: String,
+}
+
+/// Path of the messaging configuration file inside the container.
+const MESSAGING_CONFIG_PATH: &str = "/katana-ci/messaging.json";
+/// Directory of the mounted contract artifacts inside the container.
+const CONTRACTS_DIR: &str = "/katana-ci/contracts";
+/// Database directory inside the container, when persisted on the host.
+const DB_DIR: &str = "/katana-ci/db";
+
+#[derive(Default)]
 pub struct KatanaDockerOptions {
+    /// Alias of the image to use instead of the default one.
+    pub image_alias: Option<String>,
     pub port: u32,
     pub block_time: Option<u32>,
     pub no_mining: Option<bool>,
+    /// Absolute host path of the L1 messaging configuration file.
+    pub messaging_config: Option<String>,
+    /// Port of the metrics endpoint, disabled if not set.
+    pub metrics_port: Option<u32>,
+    /// Absolute host paths of contract artifacts, mounted
+    /// by file name into `CONTRACTS_DIR`.
+    pub declare_paths: Vec<String>,
+    /// Absolute host path of the directory persisting the database.
+    pub persist_dir: Option<String>,
+    /// Runs Katana in dev mode, with the dev contracts predeployed.
+    pub dev: bool,
+    /// RPC endpoint to fork the network from.
+    pub fork_rpc_url: Option<String>,
+    /// Created ahead of any start, for the warm pool.
+    pub pooled: bool,
 }
 
 impl KatanaDockerOptions {
+    /// Whether only the port is set, the options of the warm pool containers.
+    pub fn is_poolable(&self) -> bool {
+        let Self {
+            image_alias,
+            port: _,
+            block_time,
+            no_mining,
+            messaging_config,
+            metrics_port,
+            declare_paths,
+            persist_dir,
+            dev,
+            fork_rpc_url,
+            pooled: _,
+        } = self;
+
+        image_alias.is_none()
+            && block_time.is_none()
+            && no_mining.is_none()
+            && messaging_config.is_none()
+            && metrics_port.is_none()
+            && declare_paths.is_empty()
+            && persist_dir.is_none()
+            && !dev
+            && fork_rpc_url.is_none()
+    }
+
     pub fn to_str_vec(&self) -> Vec<String> {
         let mut out = vec![
             "katana".to_string(),
@@ -43,6 +500,11 @@ impl KatanaDockerOptions {
             "--disable-fee".to_string(),
         ];
 
+        // Fees stay disabled in dev mode.
+        if self.dev {
+            out.push("--dev".to_string());
+        }
+
         if let Some(v) = self.block_time {
             out.push("--block-time".to_string());
             out.push(v.to_string());
@@ -53,6 +515,50 @@ impl KatanaDockerOptions {
             out.push(v.to_string());
         }
 
+        if self.messaging_config.is_some() {
+            out.push("--messaging".to_string());
+            out.push(MESSAGING_CONFIG_PATH.to_string());
+        }
+
+        if let Some(v) = self.metrics_port {
+            out.push("--metrics".to_string());
+            out.push(format!("0.0.0.0:{v}"));
+        }
+
+        if let Some(url) = &self.fork_rpc_url {
+            out.push("--rpc-url".to_string());
+            out.push(url.clone());
+        }
+
+        if self.persist_dir.is_some() {
+            out.push("--db-dir".to_string());
+            out.push(DB_DIR.to_string());
+        }
+
+        out
+    }
+
+    /// Host files bind-mounted (read-only) into the container,
+    /// and the database directory (read-write) if persisted.
+    pub fn volumes(&self) -> Vec<String> {
+        let mut out = vec![];
+
+        if let Some(path) = &self.messaging_config {
+            out.push(format!("{path}:{MESSAGING_CONFIG_PATH}:ro"));
+        }
+
+        for path in &self.declare_paths {
+            let file_name = std::path::Path::new(path)
+                .file_name()
+                .map(|f| f.to_string_lossy())
+                .unwrap_or_default();
+            out.push(format!("{path}:{CONTRACTS_DIR}/{file_name}:ro"));
+        }
+
+        if let Some(path) = &self.persist_dir {
+            out.push(format!("{path}:{DB_DIR}"));
+        }
+
         out
     }
 }
@@ -61,47 +567,397 @@ impl DockerManager {
     pub fn new(image: &str) -> Self {
         Self {
             docker: Docker::new(),
+            daemon: DaemonClient::from_env(),
             image: image.to_string(),
+            restart_policy: RestartPolicy::default(),
+            registry_auth: None,
+            upstream: Upstream::default(),
+            publish_interface: DEFAULT_PUBLISH_INTERFACE.to_string(),
+            images: HashMap::new(),
+            breaker: None,
+            readonly_rootfs: false,
+            limit: None,
+            ips: Default::default(),
+        }
+    }
+
+    /// Manager of the docker daemon at the given `http://` or `unix://` url.
+    #[cfg(test)]
+    pub fn with_docker_host(mut self, host: &str) -> Self {
+        self.docker = Docker::host(host.parse().unwrap());
+        self.daemon = DaemonClient::new(host, None, false);
+        self
+    }
+
+    /// Alternative images the users can pick by alias.
+    pub fn with_images(mut self, images: HashMap<String, String>) -> Self {
+        self.images = images;
+        self
+    }
+
+    /// Image of the given alias, the default image if not set.
+    pub fn resolve_image(&self, alias: Option<&str>) -> Option<&str> {
+        match alias {
+            Some(alias) => self.images.get(alias).map(String::as_str),
+            None => Some(&self.image),
+        }
+    }
+
+    /// Host interface the ports of the containers are published on.
+    pub fn with_publish_interface(mut self, interface: &str) -> Self {
+        self.publish_interface = interface.to_string();
+        self
+    }
+
+    /// Fails fast the container creations and starts after
+    /// consecutive failures of the daemon.
+    pub fn with_circuit_breaker(mut self, breaker: Option<CircuitBreaker>) -> Self {
+        self.breaker = breaker;
+        self
+    }
+
+    /// Bounds the number of concurrent container creations, starts and
+    /// removals, shared by the requests, the reaper and the sweep.
+    pub fn with_max_concurrency(mut self, max: Option<usize>) -> Self {
+        self.limit = max.map(|max| Arc::new(Semaphore::new(max)));
+        self
+    }
+
+    /// Runs a call once a slot of the concurrency limit, if any, is free.
+    async fn limited<T>(&self, call: impl Future<Output = T>) -> T {
+        let _permit = match &self.limit {
+            Some(limit) => Some(limit.acquire().await.expect("docker limit closed")),
+            None => None,
+        };
+
+        call.await
+    }
+
+    /// Runs a call through the circuit breaker, if any.
+    async fn guarded<T>(
+        &self,
+        call: impl Future<Output = Result<T, DockerError>>,
+    ) -> Result<T, DockerError> {
+        let breaker = match &self.breaker {
+            Some(breaker) => breaker,
+            None => return call.await,
+        };
+
+        breaker.check().map_err(DockerError::Unavailable)?;
+        let res = call.await;
+        breaker.record(!res.as_ref().is_err_and(DockerError::is_daemon_failure));
+        res
+    }
+
+    pub fn with_readonly_rootfs(mut self, readonly_rootfs: bool) -> Self {
+        self.readonly_rootfs = readonly_rootfs;
+        self
+    }
+
+    pub fn with_upstream(mut self, upstream: Upstream) -> Self {
+        self.upstream = upstream;
+        self
+    }
+
+    pub fn with_registry_auth(mut self, registry_auth: Option<RegistryCredentials>) -> Self {
+        self.registry_auth = registry_auth;
+        self
+    }
+
+    /// Pulls the default and aliased images not present locally,
+    /// using the registry credentials if any.
+    pub async fn ensure_image(&self) -> Result<(), DockerError> {
+        for image in std::iter::once(&self.image).chain(self.images.values()) {
+            self.ensure_image_with_progress(image, |_| {}).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Pulls the given image if not present locally, reporting
+    /// each pull progress event.
+    pub async fn ensure_image_with_progress(
+        &self,
+        image: &str,
+        mut on_progress: impl FnMut(&serde_json::Value),
+    ) -> Result<(), DockerError> {
+        match self.docker.images().get(image).inspect().await {
+            Ok(_) => {
+                trace!("image {image} already present");
+                return Ok(());
+            }
+            Err(ShipliftError::Fault { code, .. }) if code.as_u16() == 404 => {}
+            Err(e) => return Err(e.into()),
+        }
+
+        info!("pulling image {image}");
+
+        // A `name@sha256:...` reference is resolved by docker
+        // as is, without a tag.
+        let mut builder = PullOptions::builder();
+        builder.image(image);
+        if let Some(auth) = &self.registry_auth {
+            builder.auth(auth.to_registry_auth());
+        }
+
+        let images = self.docker.images();
+        let mut pull_stream = images.pull(&builder.build());
+
+        while let Some(progress) = pull_stream.next().await {
+            let progress = progress?;
+            if let Some(e) = progress.get("error").and_then(|e| e.as_str()) {
+                return Err(DockerError::Generic(format!("can't pull {image}: {e}")));
+            }
+            trace!("pull {image}: {progress}");
+            on_progress(&progress);
+        }
+
+        info!("image {image} pulled");
+        Ok(())
+    }
+
+    pub fn with_restart_policy(mut self, restart_policy: RestartPolicy) -> Self {
+        self.restart_policy = restart_policy;
+        self
+    }
+
+    /// Digest of the local image, the pinned one if the image
+    /// is referenced by digest.
+    pub async fn image_digest(&self) -> Result<Option<String>, DockerError> {
+        if let Some((_, digest)) = self.image.split_once('@') {
+            return Ok(Some(digest.to_string()));
         }
+
+        let details = self.docker.images().get(&self.image).inspect().await?;
+
+        Ok(details
+            .repo_digests
+            .unwrap_or_default()
+            .first()
+            .and_then(|d| d.split_once('@'))
+            .map(|(_, digest)| digest.to_string()))
     }
 
     pub async fn create(&self, opts: &KatanaDockerOptions) -> Result<String, DockerError> {
-        let c = self
+        let image = self
+            .resolve_image(opts.image_alias.as_deref())
+            .ok_or(DockerError::Generic(format!(
+                "unknown image alias {:?}",
+                opts.image_alias
+            )))?;
+
+        self.guarded(self.limited(self.create_katana(image, opts)))
+            .await
+    }
+
+    async fn create_katana(
+        &self,
+        image: &str,
+        opts: &KatanaDockerOptions,
+    ) -> Result<String, DockerError> {
+        let port = opts.port.to_string();
+        let mut labels = HashMap::from([(MANAGED_LABEL, "true")]);
+        if opts.pooled {
+            labels.insert(POOL_LABEL, &port);
+        }
+
+        let mut builder = ContainerOptions::builder(image);
+        builder
+            .labels(&labels)
+            .cmd(opts.to_str_vec().iter().map(|n| &**n).collect())
+            .volumes(opts.volumes().iter().map(|n| &**n).collect())
+            .restart_policy(self.restart_policy.as_str(), 0);
+
+        // Reached through the container IP, the ports don't need to be published.
+        if self.upstream == Upstream::HostPort {
+            builder.expose(opts.port, "tcp", opts.port);
+
+            if let Some(port) = opts.metrics_port {
+                builder.expose(port, "tcp", port);
+            }
+        }
+
+        // Not supported by shiplift, the host settings are patched in the options.
+        let mut body: serde_json::Value = serde_json::from_str(&builder.build().serialize()?)
+            .map_err(|e| DockerError::Generic(e.to_string()))?;
+        bind_ports(&mut body, &self.publish_interface);
+        if self.readonly_rootfs {
+            set_readonly_rootfs(&mut body);
+        }
+
+        let id = self.create_container(body.to_string()).await?;
+
+        trace!(
+            "created {} with args {:?}",
+            id,
+            redact_launch_args(&opts.to_str_vec())
+        );
+        Ok(id)
+    }
+
+    /// Sends the container creation to the docker daemon, as shiplift can't
+    /// set the host interface of the published ports nor post a raw body.
+    async fn create_container(&self, body: String) -> Result<String, DockerError> {
+        let resp = self.daemon.post_json(CREATE_CONTAINER_PATH, body).await?;
+
+        let status = resp.status();
+        let bytes = hyper::body::to_bytes(resp.into_body())
+            .await
+            .map_err(|e| DockerError::Generic(e.to_string()))?;
+
+        if !status.is_success() {
+            return Err(ShipliftError::Fault {
+                code: status,
+                message: String::from_utf8_lossy(&bytes).into_owned(),
+            }
+            .into());
+        }
+
+        let info: ContainerCreateInfo =
+            serde_json::from_slice(&bytes).map_err(|e| DockerError::Generic(e.to_string()))?;
+        Ok(info.id)
+    }
+
+    /// Address to reach the ports of the container, according
+    /// to the upstream mode.
+    pub async fn upstream_ip(&self, container_id: &str) -> Result<IpAddr, DockerError> {
+        if self.upstream == Upstream::HostPort {
+            // Published on all the interfaces, the port is reachable on the loopback.
+            return Ok(match self.publish_interface.parse::<IpAddr>() {
+                Ok(ip) if !ip.is_unspecified() => ip,
+                _ => IpAddr::from([127, 0, 0, 1]),
+            });
+        }
+
+        if let Some(ip) = self.ips.lock().unwrap().get(container_id) {
+            return Ok(*ip);
+        }
+
+        let details = self.docker.containers().get(container_id).inspect().await?;
+        let ip = container_ip(&details.network_settings).ok_or(DockerError::Generic(format!(
+            "no IP address for container {container_id}"
+        )))?;
+
+        self.ips
+            .lock()
+            .unwrap()
+            .insert(container_id.to_string(), ip);
+        Ok(ip)
+    }
+
+    /// Checks the docker daemon is reachable.
+    pub async fn ping(&self) -> Result<(), DockerError> {
+        self.docker.ping().await?;
+        Ok(())
+    }
+
+    /// Docker status of the container, like `running` or `exited`.
+    pub async fn status(&self, container_id: &str) -> Result<String, DockerError> {
+        let details = self.docker.containers().get(container_id).inspect().await?;
+        Ok(details.state.status)
+    }
+
+    /// Inspects the state of the container, `None` if it no longer exists.
+    pub async fn inspect(&self, container_id: &str) -> Result<Option<ContainerState>, DockerError> {
+        let details = match self.docker.containers().get(container_id).inspect().await {
+            Ok(details) => details,
+            Err(ShipliftError::Fault { code, .. }) if code.as_u16() == 404 => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+
+        Ok(Some(ContainerState {
+            status: details.state.status,
+            running: details.state.running,
+            exit_code: details.state.exit_code,
+            started_at: details.state.started_at.to_rfc3339(),
+        }))
+    }
+
+    /// Lists all the containers created by the proxifier, running or not.
+    pub async fn list_managed(&self) -> Result<Vec<ManagedContainer>, DockerError> {
+        let containers = self
             .docker
             .containers()
-            .create(
-                &ContainerOptions::builder(self.image.as_ref())
-                    .expose(opts.port, "tcp", opts.port)
-                    .cmd(opts.to_str_vec().iter().map(|n| &**n).collect())
+            .list(
+                &ContainerListOptions::builder()
+                    .all()
+                    .filter(vec![ContainerFilter::LabelName(MANAGED_LABEL.to_string())])
                     .build(),
             )
             .await?;
 
-        trace!("created {} with opts {:?}", c.id, opts);
-        Ok(c.id)
+        Ok(containers
+            .into_iter()
+            .map(|c| ManagedContainer {
+                pool_port: c.labels.get(POOL_LABEL).and_then(|p| p.parse().ok()),
+                running: c.state == "running",
+                id: c.id,
+                created: c.created.timestamp(),
+            })
+            .collect())
+    }
+
+    /// Creates and removes a throwaway container of the default image, for
+    /// the runtime to prepare its layers ahead of the first start.
+    /// Returns the time it took.
+    pub async fn prewarm(&self) -> Result<Duration, DockerError> {
+        let started = Instant::now();
+
+        // Labelled as managed to be swept if it can't be removed.
+        let opts = ContainerOptions::builder(&self.image)
+            .labels(&HashMap::from([(MANAGED_LABEL, "true")]))
+            .build();
+        let info = self.limited(self.docker.containers().create(&opts)).await?;
+        trace!("created prewarm container {}", info.id);
+
+        let force = true;
+        self.remove(&info.id, force).await?;
+
+        Ok(started.elapsed())
     }
 
     pub async fn remove(&self, container_id: &str, force: bool) -> Result<(), DockerError> {
         let c = self.docker.containers().get(container_id);
+        self.ips.lock().unwrap().remove(container_id);
 
-        if force {
-            trace!("force removing {}", container_id);
-            let opts = RmContainerOptions::builder().force(true).build();
-            c.remove(opts).await?;
-        } else {
-            trace!("stopping {}", container_id);
-            c.stop(None).await?;
-            trace!("deleting {}", container_id);
-            c.delete().await?;
-        }
+        let removed = self
+            .limited(async {
+                if force {
+                    trace!("force removing {}", container_id);
+                    let opts = RmContainerOptions::builder().force(true).build();
+                    c.remove(opts).await?;
+                } else {
+                    trace!("stopping {}", container_id);
+                    c.stop(None).await?;
+                    trace!("deleting {}", container_id);
+                    c.delete().await?;
+                }
 
-        Ok(())
+                Ok(())
+            })
+            .await;
+
+        match removed {
+            // Like a container removed by hand.
+            Err(DockerError::Shiplift(ShipliftError::Fault { code, .. }))
+                if code.as_u16() == 404 =>
+            {
+                trace!("{container_id} already removed");
+                Ok(())
+            }
+            removed => removed,
+        }
     }
 
     pub async fn start(&self, container_id: &str) -> Result<(), DockerError> {
         trace!("starting {}", container_id);
-        self.docker.containers().get(container_id).start().await?;
-        Ok(())
+        // A restarted container can get another address.
+        self.ips.lock().unwrap().remove(container_id);
+        self.guarded(self.limited(async {
+            self.docker.containers().get(container_id).start().await?;
+            Ok(())
+        }))
+        .await
     }
 
     pub async fn logs(&self, container_id: &str, n: String) -> Result<String, DockerError> {
@@ -133,4 +989,491 @@ impl DockerManager {
 
         Ok(output)
     }
+
+    /// First `n` lines of the container logs.
+    pub async fn head_logs(&self, container_id: &str, n: usize) -> Result<String, DockerError> {
+        let mut lines: Vec<String> = vec![];
+
+        let mut logs_stream = self
+            .docker
+            .containers()
+            .get(container_id)
+            .logs(&LogsOptions::builder().stdout(true).stderr(true).build());
+
+        while let Some(chunk) = logs_stream.next().await {
+            let bytes = match chunk? {
+                TtyChunk::StdOut(bytes) | TtyChunk::StdErr(bytes) => bytes,
+                TtyChunk::StdIn(_) => unreachable!(),
+            };
+
+            lines.extend(String::from_utf8_lossy(&bytes).lines().map(str::to_string));
+            if lines.len() >= n {
+                lines.truncate(n);
+                break;
+            }
+        }
+
+        Ok(lines.join("\n"))
+    }
+
+    /// Follows the container logs, emitting each line through `tracing`
+    /// until the container stops or the task is aborted.
+    pub async fn forward_logs(&self, container_id: &str, name: &str) {
+        let mut lines = self.follow_logs(container_id, "all");
+
+        while let Some(line) = lines.next().await {
+            match line {
+                Ok(line) => info!(instance = name, "{line}"),
+                Err(e) => {
+                    warn!("log forwarding of {name} interrupted: {e}");
+                    break;
+                }
+            }
+        }
+
+        trace!("log forwarding of {name} ended");
+    }
+
+    /// Follows the container logs from the `n` last lines, yielding each
+    /// non-empty line until the container stops. Docker is only read as fast
+    /// as the stream is polled.
+    pub fn follow_logs<'a>(
+        &'a self,
+        container_id: &str,
+        n: &str,
+    ) -> impl Stream<Item = Result<String, DockerError>> + Unpin + 'a {
+        let logs_stream = self.docker.containers().get(container_id).logs(
+            &LogsOptions::builder()
+                .stdout(true)
+                .stderr(true)
+                .follow(true)
+                .tail(n)
+                .build(),
+        );
+
+        logs_stream.flat_map(|chunk| {
+            let lines = match chunk {
+                Ok(TtyChunk::StdOut(bytes) | TtyChunk::StdErr(bytes)) => {
+                    String::from_utf8_lossy(&bytes)
+                        .lines()
+                        .filter(|line| !line.is_empty())
+                        .map(|line| Ok(line.to_string()))
+                        .collect()
+                }
+                Ok(TtyChunk::StdIn(_)) => unreachable!(),
+                Err(e) => vec![Err(e.into())],
+            };
+            futures_util::stream::iter(lines)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{MockDocker, TempDir};
+    use hyper::{Method, StatusCode};
+
+    #[test]
+    fn image_references_are_validated_and_tagged() {
+        let digest = format!("sha256:{}", "a".repeat(64));
+        let cases = [
+            ("katana", Some("katana:latest".to_string())),
+            (
+                "arkproject/katana",
+                Some("arkproject/katana:latest".to_string()),
+            ),
+            (
+                "arkproject/katana:v1.0",
+                Some("arkproject/katana:v1.0".to_string()),
+            ),
+            (
+                &format!("arkproject/katana@{digest}"),
+                Some(format!("arkproject/katana@{digest}")),
+            ),
+            (
+                "registry.io:5000/katana",
+                Some("registry.io:5000/katana:latest".to_string()),
+            ),
+            (
+                "localhost:5000/ark/katana:dev",
+                Some("localhost:5000/ark/katana:dev".to_string()),
+            ),
+            ("", None),
+            ("Katana", None),
+            ("katana image", None),
+            ("katana:", None),
+            ("katana@sha256:abc", None),
+            ("reg istry.io/katana", None),
+        ];
+
+        for (image, normalized) in cases {
+            assert_eq!(normalize_image_reference(image), normalized, "{image:?}");
+        }
+    }
+
+    #[test]
+    fn dev_flag_is_only_set_when_requested() {
+        let mut opts = KatanaDockerOptions::default();
+        assert!(!opts.to_str_vec().contains(&"--dev".to_string()));
+
+        opts.dev = true;
+        let args = opts.to_str_vec();
+        assert_eq!(args.iter().filter(|a| *a == "--dev").count(), 1);
+        assert!(args.contains(&"--disable-fee".to_string()));
+    }
+
+    #[test]
+    fn bind_ports_sets_the_host_interface() {
+        let mut builder = ContainerOptions::builder("arkproject/katana:test");
+        builder.expose(5050, "tcp", 5050).expose(9100, "tcp", 9100);
+        let mut body: serde_json::Value =
+            serde_json::from_str(&builder.build().serialize().unwrap()).unwrap();
+
+        bind_ports(&mut body, "10.0.0.1");
+
+        for port in ["5050/tcp", "9100/tcp"] {
+            assert_eq!(
+                body["HostConfig"]["PortBindings"][port][0]["HostIp"],
+                "10.0.0.1"
+            );
+        }
+    }
+
+    #[test]
+    fn container_ip_falls_back_on_the_attached_networks() {
+        let mut settings: NetworkSettings = serde_json::from_value(serde_json::json!({
+            "Bridge": "",
+            "Gateway": "",
+            "IPAddress": "",
+            "IPPrefixLen": 0,
+            "MacAddress": "",
+            "Networks": {
+                "ci": {
+                    "NetworkID": "",
+                    "EndpointID": "",
+                    "Gateway": "",
+                    "IPAddress": "10.1.0.3",
+                    "IPPrefixLen": 16,
+                    "IPv6Gateway": "",
+                    "GlobalIPv6Address": "",
+                    "GlobalIPv6PrefixLen": 0,
+                    "MacAddress": "",
+                },
+            },
+        }))
+        .unwrap();
+        assert_eq!(container_ip(&settings), Some(IpAddr::from([10, 1, 0, 3])));
+
+        settings.ip_address = "172.17.0.4".to_string();
+        assert_eq!(container_ip(&settings), Some(IpAddr::from([172, 17, 0, 4])));
+
+        settings.ip_address.clear();
+        settings.networks.clear();
+        assert_eq!(container_ip(&settings), None);
+    }
+
+    #[tokio::test]
+    async fn container_ip_is_inspected_once_per_start() {
+        let mock = MockDocker::start().await;
+        mock.add_container("katana", "running", &[]);
+        let docker = mock.manager().with_upstream(Upstream::ContainerIp);
+
+        for _ in 0..3 {
+            let ip = docker.upstream_ip("katana").await.unwrap();
+            assert_eq!(ip, IpAddr::from([172, 17, 0, 2]));
+        }
+        assert_eq!(mock.calls(Method::GET, "/containers/katana/json").len(), 1);
+
+        docker.start("katana").await.unwrap();
+        docker.upstream_ip("katana").await.unwrap();
+        assert_eq!(mock.calls(Method::GET, "/containers/katana/json").len(), 2);
+    }
+
+    #[tokio::test]
+    async fn created_ports_are_published_on_the_configured_interface() {
+        let mock = MockDocker::start().await;
+        let docker = mock.manager().with_publish_interface("10.0.0.1");
+
+        let opts = KatanaDockerOptions {
+            port: 5050,
+            ..Default::default()
+        };
+        docker.create(&opts).await.unwrap();
+
+        let binding = &mock.created_options()["HostConfig"]["PortBindings"]["5050/tcp"][0];
+        assert_eq!(binding["HostIp"], "10.0.0.1");
+        assert_eq!(binding["HostPort"], "5050");
+    }
+
+    #[tokio::test]
+    async fn only_daemon_failures_open_the_breaker() {
+        let mock = MockDocker::start().await;
+        let docker = mock
+            .manager()
+            .with_circuit_breaker(Some(CircuitBreaker::new(2, Duration::from_secs(60))));
+        let opts = KatanaDockerOptions::default();
+
+        mock.state.lock().unwrap().failure = Some(StatusCode::CONFLICT);
+        for _ in 0..3 {
+            assert!(matches!(
+                docker.create(&opts).await,
+                Err(DockerError::Shiplift(_))
+            ));
+        }
+
+        mock.state.lock().unwrap().failure = Some(StatusCode::INTERNAL_SERVER_ERROR);
+        for _ in 0..2 {
+            assert!(docker.create(&opts).await.is_err());
+        }
+        assert!(matches!(
+            docker.create(&opts).await,
+            Err(DockerError::Unavailable(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn created_containers_carry_the_restart_policy() {
+        let mock = MockDocker::start().await;
+        let opts = KatanaDockerOptions::default();
+
+        mock.manager().create(&opts).await.unwrap();
+        assert_eq!(
+            mock.created_options()["HostConfig"]["RestartPolicy"]["Name"],
+            "no"
+        );
+
+        let policy = "on-failure".parse().unwrap();
+        mock.manager()
+            .with_restart_policy(policy)
+            .create(&opts)
+            .await
+            .unwrap();
+        assert_eq!(
+            mock.created_options()["HostConfig"]["RestartPolicy"]["Name"],
+            "on-failure"
+        );
+    }
+
+    #[tokio::test]
+    async fn concurrent_calls_stay_within_the_bound() {
+        let mock = MockDocker::start().await;
+        mock.state.lock().unwrap().call_delay = Duration::from_millis(50);
+        let opts = KatanaDockerOptions::default();
+
+        let manager = mock.manager().with_max_concurrency(Some(2));
+        let created = futures_util::future::join_all((0..6).map(|_| manager.create(&opts))).await;
+        assert!(created.iter().all(Result::is_ok));
+        assert_eq!(mock.state.lock().unwrap().max_in_flight, 2);
+
+        // Unbounded, the calls are all made at once.
+        let manager = mock.manager();
+        futures_util::future::join_all((0..6).map(|_| manager.create(&opts))).await;
+        assert_eq!(mock.state.lock().unwrap().max_in_flight, 6);
+    }
+
+    #[tokio::test]
+    async fn readonly_rootfs_is_set_when_enabled() {
+        let mock = MockDocker::start().await;
+        let opts = KatanaDockerOptions::default();
+
+        mock.manager().create(&opts).await.unwrap();
+        let host_config = &mock.created_options()["HostConfig"];
+        assert!(host_config.get("ReadonlyRootfs").is_none());
+        assert!(host_config.get("Tmpfs").is_none());
+
+        mock.manager()
+            .with_readonly_rootfs(true)
+            .create(&opts)
+            .await
+            .unwrap();
+        let host_config = &mock.created_options()["HostConfig"];
+        assert_eq!(host_config["ReadonlyRootfs"], true);
+        assert_eq!(host_config["Tmpfs"]["/tmp"], "rw,noexec,nosuid");
+    }
+
+    #[tokio::test]
+    async fn pull_carries_the_registry_credentials() {
+        let mock = MockDocker::start().await;
+        let credentials = RegistryCredentials::from_base64("dXNlcjpzZWNyZXQ=").unwrap();
+        let docker = mock.manager().with_registry_auth(Some(credentials));
+
+        docker.ensure_image().await.unwrap();
+
+        let pulls = mock.calls(Method::POST, "/images/create");
+        assert_eq!(pulls.len(), 1);
+        let auth = pulls[0].headers["X-Registry-Auth"].to_str().unwrap();
+        let auth = base64::engine::general_purpose::URL_SAFE
+            .decode(auth)
+            .unwrap();
+        let auth: serde_json::Value = serde_json::from_slice(&auth).unwrap();
+        assert_eq!(auth["username"], "user");
+        assert_eq!(auth["password"], "secret");
+    }
+
+    #[tokio::test]
+    async fn public_pull_carries_no_credentials() {
+        let mock = MockDocker::start().await;
+
+        mock.manager().ensure_image().await.unwrap();
+
+        let pulls = mock.calls(Method::POST, "/images/create");
+        assert!(!pulls[0].headers.contains_key("X-Registry-Auth"));
+    }
+
+    #[tokio::test]
+    async fn digest_pinned_image_is_created_unchanged() {
+        let mock = MockDocker::start().await;
+        let image = format!("arkproject/katana@sha256:{}", "ab".repeat(32));
+        assert_eq!(normalize_image_reference(&image), Some(image.clone()));
+
+        let docker = DockerManager::new(&image).with_docker_host(&mock.url);
+        docker
+            .create(&KatanaDockerOptions::default())
+            .await
+            .unwrap();
+
+        assert_eq!(mock.created_options()["Image"], image);
+    }
+
+    /// Answers a single container creation read from the stream,
+    /// returning the path it was posted to.
+    fn answer_create(mut stream: impl std::io::Read + std::io::Write) -> String {
+        let mut request = Vec::new();
+        let mut buf = [0; 1024];
+        let head_end = loop {
+            let n = stream.read(&mut buf).unwrap();
+            request.extend_from_slice(&buf[..n]);
+            if let Some(i) = request.windows(4).position(|w| w == b"\r\n\r\n") {
+                break i + 4;
+            }
+        };
+
+        let head = String::from_utf8_lossy(&request[..head_end]).to_lowercase();
+        let content_length: usize = head
+            .lines()
+            .find_map(|l| l.strip_prefix("content-length:"))
+            .map_or(0, |l| l.trim().parse().unwrap());
+        while request.len() < head_end + content_length {
+            let n = stream.read(&mut buf).unwrap();
+            request.extend_from_slice(&buf[..n]);
+        }
+
+        let body = r#"{"Id":"created","Warnings":[]}"#;
+        write!(
+            stream,
+            "HTTP/1.1 201 Created\r\ncontent-type: application/json\r\ncontent-length: {}\r\nconnection: close\r\n\r\n{body}",
+            body.len()
+        )
+        .unwrap();
+        stream.flush().unwrap();
+
+        head.split_whitespace().nth(1).unwrap().to_string()
+    }
+
+    /// Self-signed certificate of `localhost`, written as the client
+    /// certificate, key and CA of a docker TLS directory.
+    fn docker_tls_dir() -> (TempDir, openssl::ssl::SslAcceptor) {
+        use openssl::asn1::Asn1Time;
+        use openssl::bn::BigNum;
+        use openssl::hash::MessageDigest;
+        use openssl::pkey::PKey;
+        use openssl::rsa::Rsa;
+        use openssl::ssl::{SslAcceptor, SslMethod};
+        use openssl::x509::extension::{BasicConstraints, SubjectAlternativeName};
+        use openssl::x509::{X509Builder, X509NameBuilder};
+
+        let key = PKey::from_rsa(Rsa::generate(2048).unwrap()).unwrap();
+        let mut name = X509NameBuilder::new().unwrap();
+        name.append_entry_by_text("CN", "localhost").unwrap();
+        let name = name.build();
+
+        let mut cert = X509Builder::new().unwrap();
+        cert.set_version(2).unwrap();
+        cert.set_serial_number(&BigNum::from_u32(1).unwrap().to_asn1_integer().unwrap())
+            .unwrap();
+        cert.set_subject_name(&name).unwrap();
+        cert.set_issuer_name(&name).unwrap();
+        cert.set_pubkey(&key).unwrap();
+        cert.set_not_before(&Asn1Time::days_from_now(0).unwrap())
+            .unwrap();
+        cert.set_not_after(&Asn1Time::days_from_now(1).unwrap())
+            .unwrap();
+        cert.append_extension(BasicConstraints::new().critical().ca().build().unwrap())
+            .unwrap();
+        let san = SubjectAlternativeName::new()
+            .dns("localhost")
+            .build(&cert.x509v3_context(None, None))
+            .unwrap();
+        cert.append_extension(san).unwrap();
+        cert.sign(&key, MessageDigest::sha256()).unwrap();
+        let cert = cert.build();
+
+        let dir = TempDir::new();
+        let cert_pem = String::from_utf8(cert.to_pem().unwrap()).unwrap();
+        dir.file("cert.pem", &cert_pem);
+        dir.file("ca.pem", &cert_pem);
+        let key_pem = key.private_key_to_pem_pkcs8().unwrap();
+        dir.file("key.pem", &String::from_utf8(key_pem).unwrap());
+
+        let mut acceptor = SslAcceptor::mozilla_intermediate_v5(SslMethod::tls()).unwrap();
+        acceptor.set_private_key(&key).unwrap();
+        acceptor.set_certificate(&cert).unwrap();
+        (dir, acceptor.build())
+    }
+
+    #[tokio::test]
+    async fn daemon_client_posts_over_the_unix_socket() {
+        let dir = TempDir::new();
+        let socket = dir.0.join("docker.sock");
+        let listener = std::os::unix::net::UnixListener::bind(&socket).unwrap();
+        let served = std::thread::spawn(move || answer_create(listener.accept().unwrap().0));
+
+        let daemon = DaemonClient::new(&format!("unix://{}", socket.display()), None, false);
+        assert!(matches!(daemon, DaemonClient::Unix { .. }));
+        let resp = daemon
+            .post_json("/containers/create", "{}".to_string())
+            .await;
+
+        assert_eq!(resp.unwrap().status(), StatusCode::CREATED);
+        assert_eq!(served.join().unwrap(), "/containers/create");
+    }
+
+    #[tokio::test]
+    async fn daemon_client_posts_over_tcp() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let host = format!("tcp://{}", listener.local_addr().unwrap());
+        let served = std::thread::spawn(move || answer_create(listener.accept().unwrap().0));
+
+        let daemon = DaemonClient::new(&host, None, false);
+        assert!(matches!(daemon, DaemonClient::Tcp { .. }));
+        let resp = daemon
+            .post_json("/containers/create", "{}".to_string())
+            .await;
+
+        assert_eq!(resp.unwrap().status(), StatusCode::CREATED);
+        assert_eq!(served.join().unwrap(), "/containers/create");
+    }
+
+    #[tokio::test]
+    async fn daemon_client_posts_over_tls() {
+        let (certs, acceptor) = docker_tls_dir();
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let host = format!("tcp://localhost:{}", listener.local_addr().unwrap().port());
+        let served = std::thread::spawn(move || {
+            let stream = listener.accept().unwrap().0;
+            answer_create(acceptor.accept(stream).unwrap())
+        });
+
+        let cert_path = certs.0.to_string_lossy().to_string();
+        let verify = true;
+        let daemon = DaemonClient::new(&host, Some(&cert_path), verify);
+        assert!(matches!(daemon, DaemonClient::Tls { .. }));
+        let resp = daemon
+            .post_json("/containers/create", "{}".to_string())
+            .await;
+
+        assert_eq!(resp.unwrap().status(), StatusCode::CREATED);
+        assert_eq!(served.join().unwrap(), "/containers/create");
+    }
 }
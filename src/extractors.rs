@@ -1,24 +1,40 @@
 use axum::{
     async_trait,
     extract::rejection::TypedHeaderRejectionReason,
-    extract::{FromRef, FromRequestParts},
+    extract::{FromRef, FromRequestParts, MatchedPath},
     headers::{self, authorization::Bearer, Authorization},
     http::{header, request::Parts, StatusCode},
     response::{IntoResponse, Response},
     RequestPartsExt, TypedHeader,
 };
 
+use std::sync::Arc;
 use tracing::error;
 
-use crate::db::{DbError, ProxifierDb, SqlxDb};
+use crate::config::Config;
+use crate::db::{DbError, InstanceInfo, ProxifierDb, SqlxDb};
 
 /// Errors during authentication.
 #[derive(Debug, thiserror::Error)]
 pub enum AuthenticationError {
     #[error("Unauthorized: {0}")]
     Unauthorized(String),
+    #[error("Forbidden: {0}")]
+    Forbidden(String),
     #[error("Database error: {0}")]
     DbError(DbError),
+    #[error("Database busy: {0}")]
+    Unavailable(DbError),
+}
+
+impl From<DbError> for AuthenticationError {
+    fn from(e: DbError) -> Self {
+        if e.is_pool_timeout() {
+            Self::Unavailable(e)
+        } else {
+            Self::DbError(e)
+        }
+    }
 }
 
 impl IntoResponse for AuthenticationError {
@@ -28,17 +44,98 @@ impl IntoResponse for AuthenticationError {
                 error!("{s}");
                 StatusCode::UNAUTHORIZED.into_response()
             }
+            Self::Forbidden(s) => {
+                error!("{s}");
+                StatusCode::FORBIDDEN.into_response()
+            }
             Self::DbError(e) => {
                 error!("{e}");
                 StatusCode::INTERNAL_SERVER_ERROR.into_response()
             }
+            Self::Unavailable(e) => {
+                error!("{e}");
+                (
+                    StatusCode::SERVICE_UNAVAILABLE,
+                    [(header::RETRY_AFTER, "1")],
+                )
+                    .into_response()
+            }
         }
     }
 }
 
+/// API version requested by the client through the `Accept-Version`
+/// header, defaulting to the legacy version `1`.
+#[derive(Debug, Clone, Copy)]
+pub struct ApiVersion(pub u32);
+
+#[async_trait]
+impl<S> FromRequestParts<S> for ApiVersion
+where
+    S: Send + Sync,
+{
+    type Rejection = (StatusCode, String);
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        match parts.headers.get("accept-version") {
+            Some(v) => v
+                .to_str()
+                .ok()
+                .and_then(|v| v.trim().parse().ok())
+                .map(ApiVersion)
+                .ok_or((
+                    StatusCode::BAD_REQUEST,
+                    "Invalid Accept-Version header".to_string(),
+                )),
+            None => Ok(ApiVersion(1)),
+        }
+    }
+}
+
+/// Maximum length of a CI label header value.
+const MAX_CI_LABEL_LENGTH: usize = 256;
+
+/// CI metadata of a request, from the `X-CI-System` and `X-CI-Run-Id` headers.
+#[derive(Debug, Default)]
+pub struct CiLabels {
+    pub system: Option<String>,
+    pub run_id: Option<String>,
+}
+
+#[async_trait]
+impl<S> FromRequestParts<S> for CiLabels
+where
+    S: Send + Sync,
+{
+    type Rejection = (StatusCode, String);
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        let label = |name: &str| match parts.headers.get(name) {
+            Some(v) => v
+                .to_str()
+                .ok()
+                .map(str::trim)
+                .filter(|v| !v.is_empty() && v.len() <= MAX_CI_LABEL_LENGTH)
+                .map(|v| Some(v.to_string()))
+                .ok_or((StatusCode::BAD_REQUEST, format!("Invalid {name} header"))),
+            None => Ok(None),
+        };
+
+        Ok(CiLabels {
+            system: label("x-ci-system")?,
+            run_id: label("x-ci-run-id")?,
+        })
+    }
+}
+
 #[derive(Debug)]
 pub struct AuthenticatedUser {
     pub api_key: String,
+    pub name: String,
+    pub max_instances: Option<u32>,
+    pub is_admin: bool,
+    /// Start options the user can set, all of them if not set.
+    pub allowed_options: Option<Vec<String>>,
 }
 
 #[async_trait]
@@ -60,12 +157,14 @@ where
 
         let db = SqlxDb::from_ref(state);
 
-        match db
-            .user_from_api_key(&api_key)
-            .await
-            .map_err(AuthenticationError::DbError)?
-        {
-            Some(_u) => Ok(AuthenticatedUser { api_key }),
+        match db.user_from_api_key(&api_key).await? {
+            Some(u) => Ok(AuthenticatedUser {
+                api_key,
+                name: u.name,
+                max_instances: u.max_instances,
+                is_admin: u.is_admin,
+                allowed_options: u.allowed_options,
+            }),
             None => Err(AuthenticationError::Unauthorized(format!(
                 "API-KEY {api_key}"
             ))),
@@ -73,6 +172,115 @@ where
     }
 }
 
+/// Authenticated user with the admin flag.
+#[derive(Debug)]
+pub struct AdminUser(pub AuthenticatedUser);
+
+#[async_trait]
+impl<S> FromRequestParts<S> for AdminUser
+where
+    SqlxDb: FromRef<S>,
+    S: Send + Sync,
+{
+    type Rejection = AuthenticationError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let user = AuthenticatedUser::from_request_parts(parts, state).await?;
+
+        if user.is_admin {
+            Ok(AdminUser(user))
+        } else {
+            Err(AuthenticationError::Forbidden(format!(
+                "user {} is not admin",
+                user.name
+            )))
+        }
+    }
+}
+
+/// Caller of the instance-scoped routes, a user or the bearer
+/// of an instance token.
+#[derive(Debug)]
+pub enum InstanceCaller {
+    User(AuthenticatedUser),
+    /// Token only granting access to the instance it was issued for.
+    InstanceToken(String),
+    /// Caller without credentials, on a route made public.
+    Public,
+}
+
+impl InstanceCaller {
+    /// Name of the user, or `instance token` for a token bearer.
+    pub fn name(&self) -> &str {
+        match self {
+            Self::User(user) => &user.name,
+            Self::InstanceToken(_) => "instance token",
+            Self::Public => "anonymous",
+        }
+    }
+
+    /// Checks the caller can access the given instance, a user
+    /// only accessing the instances it owns.
+    pub fn can_access(&self, instance: &InstanceInfo) -> bool {
+        match self {
+            Self::User(user) => user.api_key == instance.api_key,
+            Self::InstanceToken(token) => instance.instance_token.as_ref() == Some(token),
+            Self::Public => true,
+        }
+    }
+
+    /// Rejection of a denied access: `403` for a user not owning
+    /// the instance, `401` for an invalid token.
+    pub fn rejection(&self) -> (StatusCode, &'static str) {
+        match self {
+            Self::User(_) => (StatusCode::FORBIDDEN, "Instance not owned"),
+            _ => (StatusCode::UNAUTHORIZED, "Invalid token"),
+        }
+    }
+}
+
+#[async_trait]
+impl<S> FromRequestParts<S> for InstanceCaller
+where
+    SqlxDb: FromRef<S>,
+    Arc<Config>: FromRef<S>,
+    S: Send + Sync,
+{
+    type Rejection = AuthenticationError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        match AuthenticatedUser::from_request_parts(parts, state).await {
+            Ok(user) => Ok(Self::User(user)),
+            // Not a user api-key, checked against the instance token.
+            Err(AuthenticationError::Unauthorized(_)) => {
+                match extract_authorization_bearer(parts).await {
+                    Some(bearer) => Ok(Self::InstanceToken(bearer.token().to_string())),
+                    None if is_public_route(parts, state).await => Ok(Self::Public),
+                    None => Err(AuthenticationError::Unauthorized("no bearer".to_string())),
+                }
+            }
+            Err(e) => Err(e),
+        }
+    }
+}
+
+/// Whether the matched route, like `/:name/logs`, is a public instance route.
+async fn is_public_route<S>(parts: &mut Parts, state: &S) -> bool
+where
+    Arc<Config>: FromRef<S>,
+    S: Send + Sync,
+{
+    let config = Arc::<Config>::from_ref(state);
+
+    match parts.extract::<MatchedPath>().await {
+        Ok(path) => path
+            .as_str()
+            .strip_prefix("/:name/")
+            .is_some_and(|route| config.public_routes.iter().any(|r| r == route)),
+        Err(_) => false,
+    }
+}
+
 /// Extract authorization bearer from headers.
 async fn extract_authorization_bearer(
     parts: &mut Parts,
@@ -98,6 +306,7 @@ async fn extract_authorization_bearer(
     }
 }
 
+#[allow(dead_code)]
 struct DatabaseConnection(sqlx::AnyPool);
 
 #[async_trait]
@@ -119,3 +328,143 @@ where
         Ok(Self(pool))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{handlers, test_utils};
+    use axum::http::Request;
+    use std::time::{Duration, Instant};
+    use tower::ServiceExt;
+
+    fn user(api_key: &str) -> AuthenticatedUser {
+        AuthenticatedUser {
+            api_key: api_key.to_string(),
+            name: api_key.to_string(),
+            max_instances: None,
+            is_admin: false,
+            allowed_options: None,
+        }
+    }
+
+    #[test]
+    fn users_only_access_their_instances() {
+        let mut instance = test_utils::instance("owned", "owner-key", 5060);
+        instance.instance_token = Some("token".to_string());
+
+        assert!(InstanceCaller::User(user("owner-key")).can_access(&instance));
+        assert!(!InstanceCaller::User(user("other-key")).can_access(&instance));
+        assert!(InstanceCaller::InstanceToken("token".to_string()).can_access(&instance));
+        assert!(!InstanceCaller::InstanceToken("other".to_string()).can_access(&instance));
+    }
+
+    #[tokio::test]
+    async fn public_logs_are_read_anonymously_but_stop_needs_credentials() {
+        let docker = test_utils::MockDocker::start().await;
+        let mut state = test_utils::docker_state(&docker).await;
+        test_utils::configure(&mut state, |c| c.public_routes = vec!["logs".to_string()]);
+        let mut db = state.db.clone();
+        db.instance_add(&test_utils::instance("shared", "owner-key", 5060))
+            .await
+            .unwrap();
+        docker.add_container("container-shared", "running", &[]);
+        docker.state.lock().unwrap().logs = "started\n".to_string();
+
+        let app = axum::Router::new()
+            .route("/:name/logs", axum::routing::get(handlers::logs_katana))
+            .route("/:name/stop", axum::routing::get(handlers::stop_katana))
+            .with_state(state);
+        let get = |uri: &str| {
+            app.clone()
+                .oneshot(Request::get(uri).body(hyper::Body::empty()).unwrap())
+        };
+
+        let resp = get("/shared/logs").await.unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+        let logs = hyper::body::to_bytes(resp.into_body()).await.unwrap();
+        assert_eq!(logs, "started\n");
+
+        let resp = get("/shared/stop").await.unwrap();
+        assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+        assert!(db.instance_from_name("shared").await.unwrap().is_some());
+    }
+
+    #[tokio::test]
+    async fn instance_token_only_proxies_to_its_instance() {
+        let state = test_utils::state().await;
+        let mut db = state.db.clone();
+        let upstream = test_utils::katana().await;
+        for name in ["mine", "other"] {
+            let mut instance = test_utils::external_instance(name, "owner-key", upstream);
+            instance.instance_token = Some(format!("{name}-token"));
+            db.instance_add(&instance).await.unwrap();
+        }
+
+        let app = axum::Router::new()
+            .route("/start", axum::routing::get(handlers::start_katana))
+            .route(
+                "/:name/katana",
+                axum::routing::post(handlers::proxy_request_katana),
+            )
+            .with_state(state);
+
+        for (token, status) in [
+            (Some("mine-token"), StatusCode::OK),
+            (Some("other-token"), StatusCode::UNAUTHORIZED),
+            (None, StatusCode::UNAUTHORIZED),
+        ] {
+            let mut req =
+                Request::post("/mine/katana").header(header::CONTENT_TYPE, "application/json");
+            if let Some(token) = token {
+                req = req.header(header::AUTHORIZATION, format!("Bearer {token}"));
+            }
+            let body = r#"{"jsonrpc":"2.0","method":"starknet_chainId","params":[],"id":1}"#;
+            let resp = app
+                .clone()
+                .oneshot(req.body(hyper::Body::from(body)).unwrap())
+                .await
+                .unwrap();
+            assert_eq!(resp.status(), status, "{token:?}");
+        }
+
+        // Not an api-key, to start other instances with.
+        let req = Request::get("/start")
+            .header(header::AUTHORIZATION, "Bearer mine-token")
+            .body(hyper::Body::empty())
+            .unwrap();
+        let resp = app.oneshot(req).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+    }
+
+    #[tokio::test]
+    async fn saturated_pool_rejects_the_authentications_with_a_503() {
+        let db = SqlxDb::new_any("sqlite::memory:", 1, Duration::from_millis(50))
+            .await
+            .unwrap();
+        db.migrate().await.unwrap();
+        test_utils::user(&mut db.clone(), "user", "user-key").await;
+
+        // Holds the single connection of the pool.
+        let _conn = db.get_pool_ref().acquire().await.unwrap();
+
+        let started = Instant::now();
+        let burst = (0..5).map(|_| {
+            let db = db.clone();
+            async move {
+                let req = Request::builder()
+                    .header(header::AUTHORIZATION, "Bearer user-key")
+                    .body(())
+                    .unwrap();
+                let (mut parts, _) = req.into_parts();
+                AuthenticatedUser::from_request_parts(&mut parts, &db).await
+            }
+        });
+
+        for res in futures_util::future::join_all(burst).await {
+            let resp = res.unwrap_err().into_response();
+            assert_eq!(resp.status(), StatusCode::SERVICE_UNAVAILABLE);
+            assert_eq!(resp.headers()[header::RETRY_AFTER], "1");
+        }
+        assert!(started.elapsed() < Duration::from_secs(1));
+    }
+}
@@ -0,0 +1,93 @@
+//! gRPC health service, for the service meshes probing the
+//! proxifier with the gRPC health protocol instead of HTTP.
+//!
+//! The overall status (empty service name) is `SERVING` while the
+//! database and docker are reachable, `NOT_SERVING` otherwise.
+use axum::extract::FromRef;
+use std::net::SocketAddr;
+use std::time::Duration;
+use tokio::task::JoinHandle;
+use tonic_health::server::HealthReporter;
+use tonic_health::ServingStatus;
+use tracing::{debug, error, info, warn};
+
+use crate::db::{ProxifierDb, SqlxDb};
+use crate::docker_manager::DockerManager;
+use crate::AppState;
+
+/// Interval between two checks of the dependencies.
+const CHECK_INTERVAL: Duration = Duration::from_secs(5);
+
+pub fn spawn(state: AppState, addr: SocketAddr) -> JoinHandle<()> {
+    let (reporter, service) = tonic_health::server::health_reporter();
+
+    tokio::spawn(report_status(state, reporter));
+
+    info!("💓 gRPC health service on {addr}");
+    tokio::spawn(async move {
+        if let Err(e) = tonic::transport::Server::builder()
+            .add_service(service)
+            .serve(addr)
+            .await
+        {
+            error!("gRPC health service stopped: {e}");
+        }
+    })
+}
+
+async fn report_status(state: AppState, mut reporter: HealthReporter) {
+    let mut ticker = tokio::time::interval(CHECK_INTERVAL);
+    let mut last = None;
+
+    loop {
+        ticker.tick().await;
+
+        let status = check(&state).await;
+        if last != Some(status) {
+            if status == ServingStatus::NotServing {
+                warn!("gRPC health status now {status}");
+            } else {
+                info!("gRPC health status now {status}");
+            }
+            last = Some(status);
+        }
+
+        reporter.set_service_status("", status).await;
+    }
+}
+
+/// Status reported for the current state of the dependencies.
+async fn check(state: &AppState) -> ServingStatus {
+    let db = SqlxDb::from_ref(state);
+    let docker = DockerManager::from_ref(state);
+
+    if let Err(e) = db.stats().await {
+        debug!("gRPC health check can't reach the database: {e}");
+        return ServingStatus::NotServing;
+    }
+
+    if let Err(e) = docker.ping().await {
+        debug!("gRPC health check can't reach docker: {e}");
+        return ServingStatus::NotServing;
+    }
+
+    ServingStatus::Serving
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{self, MockDocker};
+    use axum::http::StatusCode;
+
+    #[tokio::test]
+    async fn unreachable_docker_is_not_serving() {
+        let docker = MockDocker::start().await;
+        let state = test_utils::docker_state(&docker).await;
+
+        assert_eq!(check(&state).await, ServingStatus::Serving);
+
+        docker.state.lock().unwrap().failure = Some(StatusCode::INTERNAL_SERVER_ERROR);
+        assert_eq!(check(&state).await, ServingStatus::NotServing);
+    }
+}
@@ -0,0 +1,49 @@
+//! Host resources checks, used for admission control.
+use std::fs;
+use tracing::warn;
+
+/// Available memory of the host in MB, read from `/proc/meminfo`.
+pub fn available_memory_mb() -> Option<u64> {
+    let meminfo = fs::read_to_string("/proc/meminfo").ok()?;
+    parse_available_memory_mb(&meminfo)
+}
+
+fn parse_available_memory_mb(meminfo: &str) -> Option<u64> {
+    meminfo
+        .lines()
+        .find_map(|l| l.strip_prefix("MemAvailable:"))
+        .and_then(|v| v.trim().trim_end_matches("kB").trim().parse::<u64>().ok())
+        .map(|kb| kb / 1024)
+}
+
+/// Checks the available memory against the required minimum.
+/// If the available memory can't be known, the check passes.
+pub fn has_enough_memory(available_mb: Option<u64>, min_free_mb: u64) -> bool {
+    match available_mb {
+        Some(available) => available >= min_free_mb,
+        None => {
+            warn!("available memory unknown, skipping memory check");
+            true
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn available_memory_is_read_from_meminfo() {
+        let meminfo = "MemTotal:       16384000 kB\nMemAvailable:    2048000 kB\n";
+        assert_eq!(parse_available_memory_mb(meminfo), Some(2000));
+        assert_eq!(parse_available_memory_mb("MemTotal: 1 kB"), None);
+    }
+
+    #[test]
+    fn memory_check_passes_above_the_minimum_only() {
+        assert!(has_enough_memory(Some(2048), 1024));
+        assert!(has_enough_memory(Some(1024), 1024));
+        assert!(!has_enough_memory(Some(512), 1024));
+        assert!(has_enough_memory(None, 1024));
+    }
+}
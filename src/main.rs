@@ -3,39 +3,78 @@
 //!
 //! This proxifier uses docker to spin up a new instance of Katana
 //! and then manage it internally using the name provided by the user.
-//! This version is fully on-memory, and will drop every managed service
-//! if killed.
+//! By default the database is in memory, and every managed service is
+//! forgotten if killed. With a database file, the state survives restarts.
 use axum::{
     body::Body,
+    error_handling::HandleErrorLayer,
     extract::FromRef,
-    routing::{get, post},
+    routing::{get, patch, post, MethodRouter},
     Router, Server,
 };
 use hyper::client::HttpConnector;
+use hyper::header::{HeaderName, HeaderValue};
+use std::collections::{HashMap, HashSet};
 use std::env;
 use std::error::Error;
 use std::fs::File;
 use std::io::{self, BufRead};
+use std::net::SocketAddr;
+use std::sync::atomic::AtomicBool;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio_util::sync::CancellationToken;
+use tower::limit::GlobalConcurrencyLimitLayer;
+use tower::ServiceBuilder;
 use tower_http::cors::{Any, CorsLayer};
+use tower_http::set_header::SetResponseHeaderLayer;
 use tracing::{debug, error, info, warn};
 use tracing_subscriber::{EnvFilter, FmtSubscriber};
 
+mod breaker;
+use breaker::CircuitBreaker;
+
+mod config;
+use config::{redact_url_password, Config};
+
 mod db;
-use db::{ProxifierDb, SqlxDb};
+use db::{DbError, ProxifierDb, SqlxDb, UserInfo};
 
 mod docker_manager;
 use docker_manager::DockerManager;
 
 mod extractors;
+mod grpc_health;
 mod handlers;
+mod host;
+mod ports;
+use ports::PortPool;
+
+mod readiness;
+mod reaper;
+#[cfg(test)]
+mod test_utils;
+mod warm_pool;
+use warm_pool::WarmPool;
 
 type HttpClient = hyper::client::Client<HttpConnector, Body>;
 
+/// Cancellation of the background tasks of each instance, like
+/// the logs forwarding, keyed by instance name.
+pub type InstanceTasks = Arc<Mutex<HashMap<String, CancellationToken>>>;
+
 #[derive(Clone)]
 pub struct AppState {
     pub db: SqlxDb,
     pub docker: DockerManager,
     pub http: HttpClient,
+    pub config: Arc<Config>,
+    pub instance_tasks: InstanceTasks,
+    pub ports: PortPool,
+    pub warm_pool: WarmPool,
+    pub traffic: Arc<handlers::ProxyTraffic>,
+    /// Set during a maintenance, new instances being refused.
+    pub draining: Arc<AtomicBool>,
 }
 
 impl FromRef<AppState> for SqlxDb {
@@ -50,6 +89,12 @@ impl FromRef<AppState> for HttpClient {
     }
 }
 
+impl FromRef<AppState> for Arc<Config> {
+    fn from_ref(state: &AppState) -> Self {
+        state.config.clone()
+    }
+}
+
 impl FromRef<AppState> for DockerManager {
     fn from_ref(state: &AppState) -> Self {
         state.docker.clone()
@@ -60,27 +105,93 @@ impl FromRef<AppState> for DockerManager {
 async fn main() -> Result<(), Box<dyn Error>> {
     init_logging()?;
 
-    let docker_image = env::var("KATANA_CI_IMAGE").expect("KATANA_CI_IMAGE is not set");
+    let config = Arc::new(Config::from_env());
 
     sqlx::any::install_default_drivers();
 
-    let mut db = SqlxDb::new_any("sqlite::memory:").await?;
+    info!("🗄️ database {}", redact_url_password(&config.db_url));
+    let mut db = SqlxDb::new_any(
+        &config.db_url,
+        config.db_max_connections,
+        config.db_acquire_timeout,
+    )
+    .await?
+    .with_user_cache_ttl(config.auth_cache_ttl);
 
-    sqlx::migrate!("./migrations")
-        .run(db.get_pool_ref())
-        .await?;
+    db.migrate().await?;
+
+    load_users_from_env(&mut db, config.strict_users).await;
+
+    let docker = DockerManager::new(&config.image)
+        .with_restart_policy(config.restart_policy)
+        .with_readonly_rootfs(config.readonly_rootfs)
+        .with_upstream(config.upstream)
+        .with_publish_interface(&config.publish_interface)
+        .with_images(config.images.clone())
+        .with_circuit_breaker(
+            config
+                .docker_breaker_threshold
+                .map(|threshold| CircuitBreaker::new(threshold, config.docker_breaker_cooldown)),
+        )
+        .with_registry_auth(config.registry_auth.clone())
+        .with_max_concurrency(config.docker_max_concurrency);
+
+    if config.auto_pull {
+        docker.ensure_image().await?;
+    }
 
-    load_users_from_env(&mut db).await;
+    prewarm_image(&config, &docker).await;
 
-    let docker = DockerManager::new(&docker_image);
-    let http: HttpClient = hyper::Client::builder().build(HttpConnector::new());
+    match docker.image_digest().await {
+        Ok(Some(digest)) => info!("using image {} ({digest})", config.image),
+        Ok(None) => info!("using image {} (no digest)", config.image),
+        Err(e) => warn!("can't resolve the digest of image {}: {e}", config.image),
+    }
+    let http = upstream_client(config.upstream_http2);
 
     let state = AppState {
         db: db.clone(),
         http,
         docker,
+        config: config.clone(),
+        instance_tasks: InstanceTasks::default(),
+        ports: PortPool::new(config.port_pool_size),
+        warm_pool: WarmPool::new(config.warm_pool_size),
+        traffic: Default::default(),
+        draining: Default::default(),
     };
 
+    reaper::recover(&state).await;
+
+    if let Some(interval) = config.vacuum_interval {
+        // Nothing to reclaim on disk for an in-memory database.
+        if db::is_in_memory(&config.db_url) {
+            info!("in-memory database, vacuum skipped");
+        } else {
+            db.spawn_vacuum(interval);
+        }
+    }
+
+    if config.port_pool_size > 0 {
+        state.ports.spawn_replenish(db.clone());
+    }
+
+    if config.warm_pool_size > 0 {
+        state.warm_pool.spawn_replenish(state.clone());
+    }
+
+    if let Some(max_uptime) = config.max_uptime {
+        reaper::spawn(state.clone(), max_uptime, config.reap_interval);
+    }
+
+    if !config.sweep_interval.is_zero() {
+        reaper::spawn_sweep(state.clone(), config.sweep_interval);
+    }
+
+    if let Some(addr) = config.grpc_health_addr {
+        grpc_health::spawn(state.clone(), addr);
+    }
+
     let dev_cors = CorsLayer::new()
         .allow_methods(Any)
         .allow_headers(Any)
@@ -88,22 +199,131 @@ async fn main() -> Result<(), Box<dyn Error>> {
 
     // build our application with a route
     let app = Router::new()
-        .route("/start", get(handlers::start_katana))
-        .route("/:name/stop", get(handlers::stop_katana))
+        .route("/health", get(handlers::health))
+        .route("/metrics", get(handlers::metrics))
+        .route(
+            "/start",
+            with_timeout(get(handlers::start_katana), config.start_timeout),
+        )
+        .route("/status", get(handlers::status_katana))
+        .route("/instances", get(handlers::list_katana))
+        .route("/compare", get(handlers::compare_katana))
+        .route("/reap", post(handlers::reap_katana))
+        .route("/reserve", post(handlers::reserve_katana))
+        .route("/admin/users", get(handlers::list_users))
+        .route("/admin/users/:name", patch(handlers::patch_user))
+        .route("/admin/reap", post(handlers::admin_reap_katana))
+        .route("/admin/ports", get(handlers::admin_ports_katana))
+        .route("/admin/config", get(handlers::admin_config))
+        .route("/admin/stats", get(handlers::admin_stats))
+        .route("/admin/audit", get(handlers::admin_audit))
+        .route("/admin/drain", post(handlers::admin_drain))
+        .route(
+            "/admin/register-external",
+            post(handlers::admin_register_external),
+        )
+        .route("/admin/undrain", post(handlers::admin_undrain))
+        .route("/:name", patch(handlers::patch_katana))
+        .route(
+            "/:name/stop",
+            with_timeout(get(handlers::stop_katana), config.stop_timeout),
+        )
         .route("/:name/logs", get(handlers::logs_katana))
+        .route("/:name/info", get(handlers::info_katana))
+        .route("/:name/status", get(handlers::container_status_katana))
+        .route("/:name/heartbeat", post(handlers::heartbeat_katana))
+        .route("/:name/lease", get(handlers::lease_katana))
+        .route("/:name/ping", get(handlers::ping_katana))
+        .route("/:name/tip", get(handlers::tip_katana))
+        .route(
+            "/:name/provider-config",
+            get(handlers::provider_config_katana),
+        )
+        .route("/:name/launch", get(handlers::launch_katana))
+        .route(
+            "/:name/default-account",
+            get(handlers::default_account_katana),
+        )
         .route("/:name/katana", post(handlers::proxy_request_katana))
-        .with_state(state)
-        .layer(dev_cors);
+        .route("/:name/katana/batch", post(handlers::batch_katana))
+        .route("/:name/ws", get(handlers::ws_katana))
+        .route("/:name/katana-metrics", get(handlers::metrics_katana))
+        .with_state(state);
+
+    let app = with_load_shedding(app, config.max_concurrency)
+        .layer(dev_cors)
+        .layer(api_version_layer());
 
     let ip = "127.0.0.1:5050";
     info!("{}", format!("📡 waiting for requests on http://{ip}..."));
     Server::bind(&ip.parse().unwrap())
-        .serve(app.into_make_service())
+        .serve(app.into_make_service_with_connect_info::<SocketAddr>())
+        .with_graceful_shutdown(shutdown_signal())
         .await?;
 
+    info!("shutting down");
+    db.close().await;
+
     Ok(())
 }
 
+/// Resolves on `SIGINT` or `SIGTERM`.
+async fn shutdown_signal() {
+    let terminate = async {
+        match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+            Ok(mut signal) => {
+                signal.recv().await;
+            }
+            Err(e) => {
+                error!("can't listen to SIGTERM: {e}");
+                std::future::pending::<()>().await;
+            }
+        }
+    };
+
+    tokio::select! {
+        _ = tokio::signal::ctrl_c() => {}
+        _ = terminate => {}
+    }
+}
+
+/// Bounds the handling time of a route, `504` being returned when exceeded.
+fn with_timeout(
+    route: MethodRouter<AppState>,
+    timeout: Option<Duration>,
+) -> MethodRouter<AppState> {
+    match timeout {
+        Some(timeout) => route.layer(
+            ServiceBuilder::new()
+                .layer(HandleErrorLayer::new(handlers::handle_timeout))
+                .timeout(timeout),
+        ),
+        None => route,
+    }
+}
+
+/// Sets the API version header on every response.
+fn api_version_layer() -> SetResponseHeaderLayer<HeaderValue> {
+    SetResponseHeaderLayer::overriding(
+        HeaderName::from_static(handlers::API_VERSION_HEADER),
+        HeaderValue::from(handlers::API_VERSION),
+    )
+}
+
+/// Sheds the requests beyond `max_concurrency` in flight, `503` being returned.
+fn with_load_shedding(app: Router, max_concurrency: Option<usize>) -> Router {
+    match max_concurrency {
+        // Each route gets its own service, the limit is shared among them.
+        Some(max_concurrency) => app.layer(
+            ServiceBuilder::new()
+                .layer(HandleErrorLayer::new(handlers::handle_overload))
+                .load_shed()
+                .layer(GlobalConcurrencyLimitLayer::new(max_concurrency)),
+        ),
+        None => app,
+    }
+}
+
 fn init_logging() -> Result<(), Box<dyn Error>> {
     const DEFAULT_LOG_FILTER: &str = "info,katana=trace";
 
@@ -118,7 +338,28 @@ fn init_logging() -> Result<(), Box<dyn Error>> {
     Ok(())
 }
 
-async fn load_users_from_env(db: &mut SqlxDb) {
+/// Creates and removes a throwaway container of the image when
+/// `KATANA_CI_PREWARM` is set, a failure being only logged.
+async fn prewarm_image(config: &Config, docker: &DockerManager) {
+    if config.prewarm {
+        match docker.prewarm().await {
+            Ok(elapsed) => info!("prewarmed image {} in {elapsed:?}", config.image),
+            Err(e) => warn!("can't prewarm image {}: {e}", config.image),
+        }
+    }
+}
+
+/// Client of the instances, speaking HTTP/2 with prior knowledge if
+/// `http2` is set, and HTTP/1.1 otherwise.
+fn upstream_client(http2: bool) -> HttpClient {
+    hyper::Client::builder()
+        .http2_only(http2)
+        .build(HttpConnector::new())
+}
+
+/// Loads the users of `KATANA_CI_USERS_FILE`. In strict mode, the
+/// startup is aborted if some of them can't be loaded.
+async fn load_users_from_env(db: &mut SqlxDb, strict: bool) {
     let file_path = match env::var("KATANA_CI_USERS_FILE") {
         Ok(path) => path,
         Err(_) => {
@@ -135,28 +376,325 @@ async fn load_users_from_env(db: &mut SqlxDb) {
         }
     };
 
-    for line in io::BufReader::new(file).lines() {
+    if let Err(e) = load_users(db, io::BufReader::new(file), strict).await {
+        eprintln!("{file_path}: {e}");
+        std::process::exit(1);
+    }
+}
+
+/// Loads the `name,api-key[,option...]` users, one per line, failing on an
+/// invalid line or, in strict mode, if some of them can't be loaded.
+async fn load_users(
+    db: &mut SqlxDb,
+    reader: impl BufRead,
+    strict: bool,
+) -> Result<UsersLoadOutcome, String> {
+    let mut outcome = UsersLoadOutcome::default();
+    let mut api_keys = HashSet::new();
+
+    for line in reader.lines() {
         match line {
             Ok(contents) => {
-                let parts: Vec<&str> = contents.split(',').collect();
+                let parts: Vec<&str> = contents.split(',').map(str::trim).collect();
+
+                if parts.len() < 2 {
+                    return Err(
+                        "File should contain at least two comma-separated strings.".to_string()
+                    );
+                }
+
+                let name = parts[0];
+                let api_key = parts[1];
+
+                let mut info = UserInfo {
+                    name: name.to_string(),
+                    api_key: api_key.to_string(),
+                    max_instances: None,
+                    is_admin: false,
+                    allowed_options: None,
+                };
+
+                for option in &parts[2..] {
+                    match option.split_once('=') {
+                        Some(("max_instances", n)) => match n.parse() {
+                            Ok(n) => info.max_instances = Some(n),
+                            Err(_) => {
+                                return Err(format!("Invalid max_instances for user {name}: {n}"))
+                            }
+                        },
+                        Some(("allowed_options", options)) => {
+                            let options: Vec<String> = options
+                                .split('|')
+                                .map(str::trim)
+                                .filter(|o| !o.is_empty())
+                                .map(str::to_string)
+                                .collect();
 
-                if parts.len() != 2 {
-                    eprintln!("File should contain two comma-separated strings.");
-                    std::process::exit(1);
+                            if let Some(o) = options
+                                .iter()
+                                .find(|o| !handlers::RESTRICTABLE_OPTIONS.contains(&o.as_str()))
+                            {
+                                return Err(format!("Unknown start option for user {name}: {o}"));
+                            }
+
+                            info.allowed_options = Some(options);
+                        }
+                        None if *option == "admin" => info.is_admin = true,
+                        _ => return Err(format!("Unknown option for user {name}: {option}")),
+                    }
                 }
 
-                let name = parts[0].trim();
-                let api_key = parts[1].trim();
+                if !api_keys.insert(api_key.to_string()) {
+                    error!("Duplicated api-key for default user {name}, skipped");
+                    outcome.duplicated += 1;
+                    continue;
+                }
 
                 match db.user_add(name, Some(api_key.to_string())).await {
-                    Ok(_) => debug!("Default user {} added", name),
-                    Err(e) => error!("Can't add default user {name}: {e}"),
+                    Ok(_) => {
+                        debug!("Default user {} added", name);
+                        outcome.loaded += 1;
+                    }
+                    // Already added by a previous run.
+                    Err(DbError::AlreadyExists(_))
+                        if matches!(
+                            db.user_from_api_key(api_key).await,
+                            Ok(Some(u)) if u.name == name
+                        ) =>
+                    {
+                        outcome.existing += 1;
+                    }
+                    Err(e) => {
+                        error!("Can't add default user {name}: {e}");
+                        outcome.failed += 1;
+                        continue;
+                    }
+                }
+
+                if let Err(e) = db.user_update(&info).await {
+                    error!("Can't update default user {name}: {e}");
+                    outcome.failed += 1;
                 }
             }
-            Err(err) => {
-                eprintln!("Failed to read line: {}", err);
-                std::process::exit(1);
+            Err(err) => return Err(format!("Failed to read line: {}", err)),
+        }
+    }
+
+    info!(
+        "default users: {} loaded, {} existing, {} duplicated, {} failed",
+        outcome.loaded, outcome.existing, outcome.duplicated, outcome.failed
+    );
+
+    if strict && outcome.duplicated + outcome.failed > 0 {
+        return Err("Default users not all loaded, aborting in strict mode.".to_string());
+    }
+
+    Ok(outcome)
+}
+
+/// Outcome of the users file loading, by user.
+#[derive(Debug, Default)]
+struct UsersLoadOutcome {
+    loaded: usize,
+    /// Already in the database, from a previous run.
+    existing: usize,
+    /// Skipped, their api-key being used by a previous line.
+    duplicated: usize,
+    failed: usize,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::InstanceState;
+    use axum::http::{header, Method, Request, StatusCode};
+    use tokio::sync::Notify;
+    use tower::ServiceExt;
+
+    #[tokio::test]
+    async fn enabled_prewarm_creates_and_removes_a_container() {
+        let docker = test_utils::MockDocker::start().await;
+        let mut state = test_utils::docker_state(&docker).await;
+
+        prewarm_image(&state.config, &state.docker).await;
+        assert!(docker.calls(Method::POST, "/containers/create").is_empty());
+
+        test_utils::configure(&mut state, |c| c.prewarm = true);
+        prewarm_image(&state.config, &state.docker).await;
+        let created = docker.calls(Method::POST, "/containers/create");
+        assert_eq!(created.len(), 1);
+        let removed = docker.calls(Method::DELETE, "/containers/");
+        assert_eq!(removed.len(), 1);
+        assert!(
+            removed[0].path.contains("force=true"),
+            "{}",
+            removed[0].path
+        );
+    }
+
+    #[tokio::test]
+    async fn duplicated_user_key_is_reported_and_fails_strict_mode() {
+        let users = "alice,alice-key\nbob,alice-key\ncarol,carol-key,admin\n";
+
+        let mut db = test_utils::db().await;
+        let outcome = load_users(&mut db, users.as_bytes(), false).await.unwrap();
+        assert_eq!((outcome.loaded, outcome.duplicated), (2, 1));
+        let alice = db.user_from_api_key("alice-key").await.unwrap().unwrap();
+        assert_eq!(alice.name, "alice");
+        assert!(db.user_from_name("bob").await.unwrap().is_none());
+
+        let mut db = test_utils::db().await;
+        let error = load_users(&mut db, users.as_bytes(), true)
+            .await
+            .unwrap_err();
+        assert_eq!(
+            error,
+            "Default users not all loaded, aborting in strict mode."
+        );
+    }
+
+    #[tokio::test]
+    async fn http2_client_reaches_an_h2_only_upstream() {
+        let make_svc = hyper::service::make_service_fn(|_| async {
+            Ok::<_, std::convert::Infallible>(hyper::service::service_fn(
+                |req: Request<Body>| async move {
+                    Ok::<_, std::convert::Infallible>(hyper::Response::new(Body::from(format!(
+                        "{:?}",
+                        req.version()
+                    ))))
+                },
+            ))
+        });
+        let server = Server::bind(&"127.0.0.1:0".parse().unwrap())
+            .http2_only(true)
+            .serve(make_svc);
+        let uri: hyper::Uri = format!("http://{}/", server.local_addr()).parse().unwrap();
+        tokio::spawn(server);
+
+        let resp = upstream_client(true).get(uri.clone()).await.unwrap();
+        assert_eq!(resp.version(), hyper::Version::HTTP_2);
+        let body = hyper::body::to_bytes(resp.into_body()).await.unwrap();
+        assert_eq!(body, "HTTP/2.0");
+
+        assert!(upstream_client(false).get(uri).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn responses_carry_the_api_version() {
+        let app = Router::new()
+            .route("/", get(|| async { StatusCode::NOT_FOUND }))
+            .layer(api_version_layer());
+
+        let resp = app
+            .oneshot(Request::get("/").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(
+            resp.headers()[handlers::API_VERSION_HEADER],
+            handlers::API_VERSION.to_string()
+        );
+    }
+
+    #[tokio::test]
+    async fn requests_beyond_the_concurrency_limit_are_shed() {
+        let entered = Arc::new(Notify::new());
+        let release = Arc::new(Notify::new());
+        let handler = {
+            let (entered, release) = (entered.clone(), release.clone());
+            move || async move {
+                entered.notify_one();
+                release.notified().await;
             }
+        };
+        let app = Router::new()
+            .route("/a", get(handler.clone()))
+            .route("/b", get(handler));
+        let app = with_load_shedding(app, Some(1));
+
+        let request = |path: &str| Request::get(path).body(Body::empty()).unwrap();
+        let in_flight = tokio::spawn(app.clone().oneshot(request("/a")));
+        entered.notified().await;
+
+        // Shed on another route as well.
+        for path in ["/a", "/b"] {
+            let resp = app.clone().oneshot(request(path)).await.unwrap();
+            assert_eq!(resp.status(), StatusCode::SERVICE_UNAVAILABLE);
         }
+
+        release.notify_one();
+        assert_eq!(in_flight.await.unwrap().unwrap().status(), StatusCode::OK);
+
+        let resp = tokio::spawn(app.oneshot(request("/b")));
+        entered.notified().await;
+        release.notify_one();
+        assert_eq!(resp.await.unwrap().unwrap().status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn timed_out_start_still_completes_its_launch() {
+        let docker = test_utils::MockDocker::start().await;
+        {
+            let mut docker = docker.state.lock().unwrap();
+            docker.serve_started = true;
+            docker.start_delay = Duration::from_millis(200);
+        }
+        let state = test_utils::docker_state(&docker).await;
+        let mut db = state.db.clone();
+        test_utils::user(&mut db, "user", "user-key").await;
+        let app = Router::new()
+            .route(
+                "/start",
+                with_timeout(get(handlers::start_katana), Some(Duration::from_millis(50))),
+            )
+            .with_state(state);
+
+        let request = Request::get("/start?key=slow")
+            .header(header::AUTHORIZATION, "Bearer user-key")
+            .body(Body::empty())
+            .unwrap();
+        let resp = app.oneshot(request).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::GATEWAY_TIMEOUT);
+
+        let name = db::get_keyed_name("user", "slow");
+        let started = async {
+            loop {
+                let instance = db.instance_from_name(&name).await.unwrap().unwrap();
+                if instance.state == InstanceState::Running {
+                    return instance;
+                }
+                tokio::time::sleep(Duration::from_millis(20)).await;
+            }
+        };
+        let instance = tokio::time::timeout(Duration::from_secs(5), started)
+            .await
+            .expect("start left halfway");
+        assert!(docker
+            .state
+            .lock()
+            .unwrap()
+            .containers
+            .contains_key(&instance.container_id));
+    }
+
+    #[tokio::test]
+    async fn slow_start_times_out_while_streams_are_unaffected() {
+        let slow = || async {
+            tokio::time::sleep(Duration::from_millis(200)).await;
+            "done"
+        };
+        let app = Router::new()
+            .route(
+                "/start",
+                with_timeout(get(slow), Some(Duration::from_millis(50))),
+            )
+            .route("/:name/logs", get(slow))
+            .with_state(test_utils::state().await);
+
+        let request = |path: &str| Request::get(path).body(Body::empty()).unwrap();
+        let resp = app.clone().oneshot(request("/start")).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::GATEWAY_TIMEOUT);
+
+        let resp = app.oneshot(request("/katana/logs")).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
     }
 }
@@ -0,0 +1,144 @@
+//! Pool of free ports, searched ahead of time to take the port
+//! selection out of the start latency.
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use tokio::sync::Notify;
+use tokio::task::JoinHandle;
+use tracing::{debug, info, trace};
+
+use crate::db::{is_host_port_free, ProxifierDb, SqlxDb};
+
+#[derive(Debug, Clone, Default)]
+pub struct PortPool {
+    size: usize,
+    ports: Arc<Mutex<VecDeque<u16>>>,
+    consumed: Arc<Notify>,
+}
+
+impl PortPool {
+    pub fn new(size: usize) -> Self {
+        Self {
+            size,
+            ..Default::default()
+        }
+    }
+
+    /// Takes a free port from the pool, or searches one if the pool is empty.
+    /// A pooled port claimed in the meantime, like a port requested
+    /// explicitly by a user, is discarded.
+    pub async fn take(&self, db: &SqlxDb) -> Option<u16> {
+        loop {
+            let pooled = self.ports.lock().unwrap().pop_front();
+            let port = match pooled {
+                Some(port) => port,
+                None => break,
+            };
+            self.consumed.notify_one();
+
+            match db.is_port_in_use(port).await {
+                Ok(false) if is_host_port_free(port) => {
+                    trace!("port {port} taken from the pool");
+                    return Some(port);
+                }
+                Ok(_) => debug!("pooled port {port} already in use"),
+                Err(_) => break,
+            }
+        }
+
+        db.get_free_port().await
+    }
+
+    /// Fills the pool, then refills it each time ports are consumed.
+    pub fn spawn_replenish(&self, db: SqlxDb) -> JoinHandle<()> {
+        info!("🔌 port pool of {} ports enabled", self.size);
+
+        let pool = self.clone();
+        tokio::spawn(async move {
+            loop {
+                pool.replenish(&db).await;
+                pool.consumed.notified().await;
+            }
+        })
+    }
+
+    async fn replenish(&self, db: &SqlxDb) {
+        while self.ports.lock().unwrap().len() < self.size {
+            let port = match db.get_free_port().await {
+                Some(port) => port,
+                None => return,
+            };
+
+            let mut ports = self.ports.lock().unwrap();
+            if !ports.contains(&port) {
+                ports.push_back(port);
+            }
+        }
+
+        trace!("port pool replenished");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils;
+    use std::time::Duration;
+
+    fn pooled(pool: &PortPool) -> Vec<u16> {
+        pool.ports.lock().unwrap().iter().copied().collect()
+    }
+
+    async fn wait_pooled(pool: &PortPool, n: usize) {
+        let filled = async {
+            while pooled(pool).len() < n {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        };
+        tokio::time::timeout(Duration::from_secs(5), filled)
+            .await
+            .expect("pool not replenished");
+    }
+
+    #[tokio::test]
+    async fn pooled_ports_are_taken_first() {
+        let db = test_utils::db().await;
+        let pool = PortPool::new(3);
+
+        pool.replenish(&db).await;
+        let ports = pooled(&pool);
+        assert_eq!(ports.len(), 3);
+
+        assert_eq!(pool.take(&db).await, Some(ports[0]));
+        assert_eq!(pooled(&pool), ports[1..]);
+    }
+
+    #[tokio::test]
+    async fn pooled_port_claimed_in_the_meantime_is_discarded() {
+        let mut db = test_utils::db().await;
+        let pool = PortPool::new(2);
+
+        pool.replenish(&db).await;
+        let ports = pooled(&pool);
+        db.instance_add(&test_utils::instance("claimed", "key", ports[0]))
+            .await
+            .unwrap();
+
+        assert_eq!(pool.take(&db).await, Some(ports[1]));
+        assert!(pooled(&pool).is_empty());
+    }
+
+    #[tokio::test]
+    async fn pool_is_replenished_once_consumed() {
+        let db = test_utils::db().await;
+        let pool = PortPool::new(2);
+        let replenish = pool.spawn_replenish(db.clone());
+
+        for _ in 0..2 {
+            wait_pooled(&pool, 2).await;
+            pool.take(&db).await.unwrap();
+        }
+
+        wait_pooled(&pool, 2).await;
+        replenish.abort();
+    }
+}
@@ -0,0 +1,187 @@
+//! Readiness wait of a freshly started Katana instance.
+//!
+//! The instance port is polled with an exponential backoff, to avoid
+//! hammering the container during the Katana boot.
+use axum::body::Body;
+use hyper::{header, Request};
+use std::net::SocketAddr;
+use std::time::Duration;
+use tokio::net::TcpStream;
+use tracing::{trace, warn};
+
+use crate::HttpClient;
+
+/// Exponential backoff schedule, doubling the delay up to a cap.
+#[derive(Debug, Clone)]
+pub struct Backoff {
+    next: Duration,
+    max: Duration,
+}
+
+impl Backoff {
+    pub fn new(initial: Duration, max: Duration) -> Self {
+        Self { next: initial, max }
+    }
+}
+
+impl Iterator for Backoff {
+    type Item = Duration;
+
+    fn next(&mut self) -> Option<Duration> {
+        let current = self.next;
+        self.next = (self.next * 2).min(self.max);
+        Some(current)
+    }
+}
+
+/// Waits for the given address to accept TCP connections.
+/// Returns false if it's still not the case after `timeout`.
+pub async fn wait_ready(addr: SocketAddr, timeout: Duration, backoff: Backoff) -> bool {
+    let poll = async {
+        for (attempt, delay) in backoff.enumerate() {
+            match TcpStream::connect(addr).await {
+                Ok(_) => return,
+                Err(e) => trace!("{addr} not ready (attempt {attempt}): {e}"),
+            }
+
+            tokio::time::sleep(delay).await;
+        }
+    };
+
+    tokio::time::timeout(timeout, poll).await.is_ok()
+}
+
+/// Calls a JSON-RPC method without parameters, returning the
+/// response, `None` if the call failed.
+async fn rpc_call(http: &HttpClient, addr: SocketAddr, method: &str) -> Option<serde_json::Value> {
+    let body = serde_json::json!({ "jsonrpc": "2.0", "method": method, "params": [], "id": 1 });
+    let req = Request::post(format!("http://{addr}/"))
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Body::from(body.to_string()))
+        .ok()?;
+
+    let resp = http.request(req).await.ok()?;
+    let bytes = hyper::body::to_bytes(resp.into_body()).await.ok()?;
+    serde_json::from_slice(&bytes).ok()
+}
+
+/// Latest block number of the instance, `None` if the RPC call failed.
+pub async fn block_number(http: &HttpClient, addr: SocketAddr) -> Option<u64> {
+    let json = rpc_call(http, addr, "starknet_blockNumber").await?;
+
+    match json.get("result").and_then(|r| r.as_u64()) {
+        Some(n) => Some(n),
+        None => {
+            warn!("{addr} returned an invalid block number: {json}");
+            None
+        }
+    }
+}
+
+/// Number and hash of the latest block of the instance,
+/// `None` if the RPC call failed.
+pub async fn block_hash_and_number(http: &HttpClient, addr: SocketAddr) -> Option<(u64, String)> {
+    let json = rpc_call(http, addr, "starknet_blockHashAndNumber").await?;
+    let result = json.get("result");
+
+    match (
+        result
+            .and_then(|r| r.get("block_number"))
+            .and_then(|n| n.as_u64()),
+        result
+            .and_then(|r| r.get("block_hash"))
+            .and_then(|h| h.as_str()),
+    ) {
+        (Some(number), Some(hash)) => Some((number, hash.to_string())),
+        _ => {
+            warn!("{addr} returned an invalid latest block: {json}");
+            None
+        }
+    }
+}
+
+/// Chain id of the instance, `None` if the RPC call failed.
+pub async fn chain_id(http: &HttpClient, addr: SocketAddr) -> Option<String> {
+    let json = rpc_call(http, addr, "starknet_chainId").await?;
+
+    match json.get("result").and_then(|r| r.as_str()) {
+        Some(id) => Some(id.to_string()),
+        None => {
+            warn!("{addr} returned an invalid chain id: {json}");
+            None
+        }
+    }
+}
+
+/// Checks that the instance answers RPC calls and, with interval
+/// mining of `block_time` milliseconds, that it produces blocks.
+pub async fn is_functional(http: &HttpClient, addr: SocketAddr, block_time: Option<u32>) -> bool {
+    let first = match block_number(http, addr).await {
+        Some(n) => n,
+        None => return false,
+    };
+
+    match block_time {
+        Some(block_time) => {
+            // Two intervals, to not race with a block being mined.
+            tokio::time::sleep(Duration::from_millis(2 * block_time as u64)).await;
+            block_number(http, addr).await.is_some_and(|n| n > first)
+        }
+        None => true,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils;
+
+    #[test]
+    fn backoff_doubles_up_to_its_cap() {
+        let delays: Vec<u64> = Backoff::new(Duration::from_millis(100), Duration::from_millis(500))
+            .take(5)
+            .map(|d| d.as_millis() as u64)
+            .collect();
+
+        assert_eq!(delays, vec![100, 200, 400, 500, 500]);
+    }
+
+    #[tokio::test]
+    async fn wait_ready_gives_up_after_the_timeout() {
+        let addr = SocketAddr::from(([127, 0, 0, 1], test_utils::closed_port()));
+        let backoff = Backoff::new(Duration::from_millis(10), Duration::from_millis(50));
+
+        let started = std::time::Instant::now();
+        assert!(!wait_ready(addr, Duration::from_millis(200), backoff).await);
+        assert!(started.elapsed() < Duration::from_secs(1));
+    }
+
+    #[tokio::test]
+    async fn wait_ready_returns_once_the_port_accepts_connections() {
+        let addr = test_utils::katana().await;
+        let backoff = Backoff::new(Duration::from_millis(10), Duration::from_millis(50));
+
+        assert!(wait_ready(addr, Duration::from_secs(1), backoff).await);
+    }
+
+    #[tokio::test]
+    async fn functional_check_requires_a_valid_block_number() {
+        let http = HttpClient::new();
+        assert!(is_functional(&http, test_utils::katana().await, None).await);
+
+        let broken = test_utils::upstream(|_| async {
+            let error =
+                r#"{"jsonrpc":"2.0","id":1,"error":{"code":-32603,"message":"Internal error"}}"#;
+            hyper::Response::new(Body::from(error))
+        })
+        .await;
+        assert!(!is_functional(&http, broken, None).await);
+    }
+
+    #[tokio::test]
+    async fn functional_check_with_interval_mining_requires_new_blocks() {
+        // Stuck at block 1.
+        let stuck = test_utils::katana().await;
+        assert!(!is_functional(&HttpClient::new(), stuck, Some(10)).await);
+    }
+}
@@ -0,0 +1,453 @@
+//! Background reaper removing the instances whose lease has expired.
+//!
+//! An instance lease starts at its creation and is extended by each
+//! heartbeat. Once the lease is older than the configured uptime,
+//! the instance is removed.
+//!
+//! A sweep also removes the managed containers that are no longer
+//! tracked in the database, for instance when their removal failed,
+//! and the expired name reservations.
+//!
+//! As the database can survive a restart, the instances whose container
+//! is gone, the expired reservations and tombstones are also cleaned up
+//! once at startup, before serving, and the warm pool containers left
+//! unclaimed are re-adopted.
+use axum::extract::FromRef;
+use futures_util::stream::{self, StreamExt};
+use std::collections::HashSet;
+use std::time::Duration;
+use tokio::task::JoinHandle;
+use tracing::{debug, error, info, warn};
+
+use crate::db::{now_timestamp, InstanceState, ProxifierDb, SqlxDb};
+use crate::docker_manager::DockerManager;
+use crate::handlers;
+use crate::warm_pool::PooledContainer;
+use crate::AppState;
+
+pub fn spawn(state: AppState, max_uptime: Duration, interval: Duration) -> JoinHandle<()> {
+    info!(
+        "🧹 reaper started (max uptime {}s, every {}s{})",
+        max_uptime.as_secs(),
+        interval.as_secs(),
+        if state.config.reap_dry_run {
+            ", dry-run"
+        } else {
+            ""
+        }
+    );
+
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(interval);
+
+        loop {
+            ticker.tick().await;
+            reap_expired(&state, max_uptime).await;
+        }
+    })
+}
+
+async fn reap_expired(state: &AppState, max_uptime: Duration) {
+    let db = SqlxDb::from_ref(state);
+    let deadline = now_timestamp() - max_uptime.as_secs() as i64;
+
+    let expired = match db.instances_seen_before(deadline).await {
+        Ok(instances) => instances,
+        Err(e) => {
+            error!("reaper can't list expired instances: {e}");
+            return;
+        }
+    };
+
+    debug!("reaper found {} expired instance(s)", expired.len());
+
+    if state.config.reap_dry_run {
+        let now = now_timestamp();
+        for instance in expired {
+            let last_seen = instance.last_seen.unwrap_or(instance.created_at);
+            info!(
+                "[dry-run] would reap instance {} (last seen {}s ago)",
+                instance.name,
+                now - last_seen
+            );
+        }
+        return;
+    }
+
+    // Removed concurrently, to not delay the next scan on a large batch
+    // while bounding the load on the docker daemon.
+    stream::iter(expired)
+        .for_each_concurrent(state.config.reap_concurrency, |instance| async move {
+            if let Ok(true) = handlers::remove_instance(state, &instance).await {
+                info!(
+                    "reaped instance {} (last seen {})",
+                    instance.name,
+                    instance.last_seen.unwrap_or(instance.created_at)
+                );
+            }
+        })
+        .await;
+}
+
+/// Cleans up the state left by a previous run, before serving.
+pub async fn recover(state: &AppState) {
+    prune_stale_instances(state).await;
+    adopt_pool_containers(state).await;
+    expire_reservations(state).await;
+    expire_tombstones(state).await;
+}
+
+pub fn spawn_sweep(state: AppState, interval: Duration) -> JoinHandle<()> {
+    info!("🧹 orphan sweep started (every {}s)", interval.as_secs());
+
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(interval);
+
+        loop {
+            ticker.tick().await;
+            // Containers younger than the interval may belong to
+            // an instance being started, not yet in the database.
+            sweep_orphans(&state, interval).await;
+            expire_reservations(&state).await;
+            expire_tombstones(&state).await;
+        }
+    })
+}
+
+async fn sweep_orphans(state: &AppState, grace: Duration) {
+    let db = SqlxDb::from_ref(state);
+    let docker = DockerManager::from_ref(state);
+
+    let tracked: HashSet<String> = match db.instance_list().await {
+        Ok(instances) => instances.into_iter().map(|i| i.container_id).collect(),
+        Err(e) => {
+            error!("sweep can't list instances: {e}");
+            return;
+        }
+    };
+
+    let containers = match docker.list_managed().await {
+        Ok(containers) => containers,
+        Err(e) => {
+            error!("sweep can't list containers: {e}");
+            return;
+        }
+    };
+
+    let created_before = now_timestamp() - grace.as_secs() as i64;
+
+    for container in containers {
+        if tracked.contains(&container.id)
+            || state.warm_pool.contains(&container.id)
+            || container.created > created_before
+        {
+            continue;
+        }
+
+        let force = true;
+        match docker.remove(&container.id, force).await {
+            Ok(_) => warn!("removed orphan container {}", container.id),
+            Err(e) => error!("can't remove orphan container {}: {e}", container.id),
+        }
+    }
+}
+
+/// Removes the instances whose container no longer exists,
+/// for instance after a crash of the host, and those left
+/// starting, their container being swept as an orphan.
+async fn prune_stale_instances(state: &AppState) {
+    let mut db = SqlxDb::from_ref(state);
+    let docker = DockerManager::from_ref(state);
+
+    let instances = match db.instance_list().await {
+        Ok(instances) => instances,
+        Err(e) => {
+            error!("can't list instances to prune: {e}");
+            return;
+        }
+    };
+
+    let live: HashSet<String> = match docker.list_managed().await {
+        Ok(containers) => containers.into_iter().map(|c| c.id).collect(),
+        Err(e) => {
+            error!("can't list containers to prune stale instances: {e}");
+            return;
+        }
+    };
+
+    let mut pruned = 0;
+    for instance in instances {
+        if instance.state == InstanceState::Reserving
+            || instance.is_external()
+            || live.contains(&instance.container_id)
+        {
+            continue;
+        }
+
+        match db.instance_rm(&instance.name).await {
+            Ok(_) => {
+                debug!(
+                    "pruned instance {} of gone container {}",
+                    instance.name, instance.container_id
+                );
+                pruned += 1;
+            }
+            Err(e) => error!("can't prune instance {}: {e}", instance.name),
+        }
+    }
+
+    info!("pruned {pruned} stale instance(s)");
+}
+
+/// Puts back in the warm pool its running containers not claimed by any
+/// instance, removing the stopped ones and those beyond the pool size.
+async fn adopt_pool_containers(state: &AppState) {
+    let db = SqlxDb::from_ref(state);
+    let docker = DockerManager::from_ref(state);
+
+    let tracked: HashSet<String> = match db.instance_list().await {
+        Ok(instances) => instances.into_iter().map(|i| i.container_id).collect(),
+        Err(e) => {
+            error!("can't list instances to adopt the warm pool: {e}");
+            return;
+        }
+    };
+
+    let containers = match docker.list_managed().await {
+        Ok(containers) => containers,
+        Err(e) => {
+            error!("can't list containers to adopt the warm pool: {e}");
+            return;
+        }
+    };
+
+    let mut adopted = 0;
+    for container in containers {
+        let port = match container.pool_port {
+            Some(port) if !tracked.contains(&container.id) => port,
+            _ => continue,
+        };
+
+        if container.running
+            && state.warm_pool.adopt(PooledContainer {
+                id: container.id.clone(),
+                port,
+            })
+        {
+            adopted += 1;
+            continue;
+        }
+
+        let force = true;
+        match docker.remove(&container.id, force).await {
+            Ok(_) => debug!("removed warm pool container {}", container.id),
+            Err(e) => error!("can't remove warm pool container {}: {e}", container.id),
+        }
+    }
+
+    if adopted > 0 {
+        info!("re-adopted {adopted} warm pool container(s)");
+    }
+}
+
+async fn expire_reservations(state: &AppState) {
+    let mut db = SqlxDb::from_ref(state);
+    let before = now_timestamp() - state.config.reservation_ttl.as_secs() as i64;
+
+    match db.reservations_expire(before).await {
+        Ok(0) => {}
+        Ok(n) => info!("expired {n} reservation(s)"),
+        Err(e) => error!("can't expire reservations: {e}"),
+    }
+}
+
+async fn expire_tombstones(state: &AppState) {
+    let mut db = SqlxDb::from_ref(state);
+
+    match db.tombstones_expire(now_timestamp()).await {
+        Ok(0) => {}
+        Ok(n) => info!("expired {n} tombstone(s)"),
+        Err(e) => error!("can't expire tombstones: {e}"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::InstanceState;
+    use crate::test_utils;
+    use crate::warm_pool::WarmPool;
+
+    #[tokio::test]
+    async fn recovery_adopts_the_warm_pool_and_drops_the_stale_reservations() {
+        let docker = test_utils::MockDocker::start().await;
+        let mut state = test_utils::docker_state(&docker).await;
+        state.warm_pool = WarmPool::new(2);
+        let mut db = SqlxDb::from_ref(&state);
+        let ttl = state.config.reservation_ttl.as_secs() as i64;
+
+        let pool = |port| [("katana-ci.managed", "true"), ("katana-ci.pool", port)];
+        docker.add_container("pooled", "running", &pool("10500"));
+        docker.add_container("stopped-pooled", "exited", &pool("10501"));
+        // Claimed by an instance before the restart.
+        docker.add_container("claimed", "running", &pool("10502"));
+        let mut claimed = test_utils::instance("claimed", "key", 10502);
+        claimed.container_id = "claimed".to_string();
+        db.instance_add(&claimed).await.unwrap();
+
+        let mut stale = test_utils::instance("stale", "key", 5060);
+        stale.state = InstanceState::Reserving;
+        db.reservation_add(&stale, "stale-token").await.unwrap();
+        test_utils::backdate(&db, "stale", now_timestamp() - ttl - 10).await;
+
+        recover(&state).await;
+
+        assert_eq!(
+            state.warm_pool.take(),
+            Some(PooledContainer {
+                id: "pooled".to_string(),
+                port: 10500
+            })
+        );
+        assert_eq!(state.warm_pool.take(), None);
+        assert!(db.instance_from_name("claimed").await.unwrap().is_some());
+        assert!(db.instance_from_name("stale").await.unwrap().is_none());
+        let containers = &docker.state.lock().unwrap().containers;
+        assert!(!containers.contains_key("stopped-pooled"));
+        assert!(containers.contains_key("claimed"));
+    }
+
+    #[tokio::test]
+    async fn instances_of_gone_containers_are_pruned() {
+        let docker = test_utils::MockDocker::start().await;
+        let state = test_utils::docker_state(&docker).await;
+        let mut db = SqlxDb::from_ref(&state);
+
+        docker.add_container(
+            "container-live",
+            "running",
+            &[("katana-ci.managed", "true")],
+        );
+        db.instance_add(&test_utils::instance("live", "key", 5060))
+            .await
+            .unwrap();
+        db.instance_add(&test_utils::instance("gone", "key", 5061))
+            .await
+            .unwrap();
+        let addr = "127.0.0.1:5062".parse().unwrap();
+        db.instance_add(&test_utils::external_instance("external", "key", addr))
+            .await
+            .unwrap();
+        let mut reserved = test_utils::instance("reserved", "key", 5063);
+        reserved.state = InstanceState::Reserving;
+        db.reservation_add(&reserved, "token").await.unwrap();
+        let mut starting = test_utils::instance("starting", "key", 5064);
+        starting.container_id = String::new();
+        starting.state = InstanceState::Starting;
+        db.reserve_port_and_add_instance(&starting).await.unwrap();
+
+        prune_stale_instances(&state).await;
+
+        for kept in ["live", "external", "reserved"] {
+            assert!(
+                db.instance_from_name(kept).await.unwrap().is_some(),
+                "{kept}"
+            );
+        }
+        for pruned in ["gone", "starting"] {
+            assert!(
+                db.instance_from_name(pruned).await.unwrap().is_none(),
+                "{pruned}"
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn recovery_drops_the_reservations_expired_while_down() {
+        let state = test_utils::state().await;
+        let mut db = SqlxDb::from_ref(&state);
+        let ttl = state.config.reservation_ttl.as_secs() as i64;
+
+        let mut stale = test_utils::instance("stale", "key", 5060);
+        stale.state = InstanceState::Reserving;
+        db.reservation_add(&stale, "stale-token").await.unwrap();
+        test_utils::backdate(&db, "stale", now_timestamp() - ttl - 10).await;
+
+        let mut fresh = test_utils::instance("fresh", "key", 5061);
+        fresh.state = InstanceState::Reserving;
+        db.reservation_add(&fresh, "fresh-token").await.unwrap();
+
+        recover(&state).await;
+
+        assert!(db.instance_from_name("stale").await.unwrap().is_none());
+        assert!(db.instance_from_name("fresh").await.unwrap().is_some());
+    }
+
+    #[tokio::test]
+    async fn expired_instances_are_removed_concurrently_within_the_bound() {
+        let docker = test_utils::MockDocker::start().await;
+        let mut state = test_utils::docker_state(&docker).await;
+        test_utils::configure(&mut state, |c| c.reap_concurrency = 2);
+        let mut db = SqlxDb::from_ref(&state);
+
+        for (i, port) in (5060..5065).enumerate() {
+            let name = format!("expired-{i}");
+            db.instance_add(&test_utils::instance(&name, "key", port))
+                .await
+                .unwrap();
+            test_utils::backdate(&db, &name, now_timestamp() - 100).await;
+            docker.add_container(&format!("container-{name}"), "running", &[]);
+        }
+        docker.state.lock().unwrap().call_delay = Duration::from_millis(50);
+
+        reap_expired(&state, Duration::ZERO).await;
+
+        let docker = docker.state.lock().unwrap();
+        assert!(docker.containers.is_empty());
+        assert_eq!(docker.max_in_flight, 2);
+    }
+
+    #[tokio::test]
+    async fn dry_run_logs_the_expired_instances_without_removing_them() {
+        let docker = test_utils::MockDocker::start().await;
+        let mut state = test_utils::docker_state(&docker).await;
+        test_utils::configure(&mut state, |c| c.reap_dry_run = true);
+        let mut db = SqlxDb::from_ref(&state);
+        db.instance_add(&test_utils::instance("expired", "key", 5060))
+            .await
+            .unwrap();
+        test_utils::backdate(&db, "expired", now_timestamp() - 100).await;
+        docker.add_container("container-expired", "running", &[]);
+
+        let (_guard, logs) = test_utils::capture_logs();
+        reap_expired(&state, Duration::from_secs(10)).await;
+
+        assert!(logs
+            .contents()
+            .contains("[dry-run] would reap instance expired (last seen"));
+        assert!(db.instance_from_name("expired").await.unwrap().is_some());
+        assert!(docker
+            .calls(hyper::Method::DELETE, "/containers")
+            .is_empty());
+    }
+
+    #[tokio::test]
+    async fn sweep_removes_the_managed_containers_without_instance() {
+        let docker = test_utils::MockDocker::start().await;
+        let state = test_utils::docker_state(&docker).await;
+        let mut db = SqlxDb::from_ref(&state);
+
+        db.instance_add(&test_utils::instance("tracked", "key", 5060))
+            .await
+            .unwrap();
+        let labels = [("katana-ci.managed", "true")];
+        docker.add_container("container-tracked", "running", &labels);
+        docker.add_container("orphan", "running", &labels);
+
+        sweep_orphans(&state, Duration::ZERO).await;
+
+        let containers = &docker.state.lock().unwrap().containers;
+        assert!(containers.contains_key("container-tracked"));
+        assert!(!containers.contains_key("orphan"));
+    }
+}
@@ -0,0 +1,719 @@
+//! Helpers shared by the tests.
+use std::collections::{BTreeMap, HashMap};
+use std::convert::Infallible;
+use std::future::Future;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use hyper::body::Bytes;
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Method, Request, Response, StatusCode};
+use serde_json::json;
+
+use crate::config::Config;
+use crate::db::{
+    now_timestamp, InstanceInfo, InstanceState, ProxifierDb, SqlxDb, EXTERNAL_CONTAINER_ID,
+};
+use crate::docker_manager::DockerManager;
+use crate::extractors::AuthenticatedUser;
+use crate::ports::PortPool;
+use crate::warm_pool::WarmPool;
+use crate::{AppState, HttpClient};
+
+/// Migrated in-memory database, with a single connection.
+pub async fn db() -> SqlxDb {
+    db_at("sqlite::memory:", 1).await
+}
+
+/// Migrated database of the given url.
+pub async fn db_at(db_url: &str, max_connections: u32) -> SqlxDb {
+    let db = SqlxDb::new_any(db_url, max_connections, Duration::from_secs(5))
+        .await
+        .unwrap();
+    db.migrate().await.unwrap();
+    db
+}
+
+/// Moves the creation time of an instance, set by the database, to the given timestamp.
+pub async fn backdate(db: &SqlxDb, name: &str, created_at: i64) {
+    sqlx::query("UPDATE instance_info SET created_at = ? WHERE instance_name = ?;")
+        .bind(created_at)
+        .bind(name)
+        .execute(db.get_pool_ref())
+        .await
+        .unwrap();
+}
+
+/// Logs captured on the current thread, see `capture_logs`.
+#[derive(Clone, Default)]
+pub struct CapturedLogs(Arc<Mutex<Vec<u8>>>);
+
+impl CapturedLogs {
+    pub fn contents(&self) -> String {
+        String::from_utf8_lossy(&self.0.lock().unwrap()).to_string()
+    }
+}
+
+impl std::io::Write for CapturedLogs {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.lock().unwrap().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Captures the logs of the current thread while the guard is alive.
+pub fn capture_logs() -> (tracing::subscriber::DefaultGuard, CapturedLogs) {
+    let logs = CapturedLogs::default();
+    let writer = logs.clone();
+    let subscriber = tracing_subscriber::fmt()
+        .with_writer(move || writer.clone())
+        .with_ansi(false)
+        .finish();
+    (tracing::subscriber::set_default(subscriber), logs)
+}
+
+/// Path of a SQLite file, unique to the test, removed with its
+/// write-ahead log when dropped.
+pub struct TempDbFile(pub String);
+
+impl TempDbFile {
+    pub fn new() -> Self {
+        let path = std::env::temp_dir().join(format!("katana-ci-{}.db", uuid::Uuid::new_v4()));
+        Self(path.to_string_lossy().to_string())
+    }
+
+    pub fn url(&self) -> String {
+        format!("sqlite:{}", self.0)
+    }
+}
+
+impl Drop for TempDbFile {
+    fn drop(&mut self) {
+        for suffix in ["", "-wal", "-shm"] {
+            let _ = std::fs::remove_file(format!("{}{suffix}", self.0));
+        }
+    }
+}
+
+/// Directory unique to the test, removed with its content when dropped.
+pub struct TempDir(pub PathBuf);
+
+impl TempDir {
+    pub fn new() -> Self {
+        let path = std::env::temp_dir().join(format!("katana-ci-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir(&path).unwrap();
+        Self(path.canonicalize().unwrap())
+    }
+
+    /// Creates a file of the given name and content in the directory.
+    pub fn file(&self, name: &str, content: &str) -> PathBuf {
+        let path = self.0.join(name);
+        std::fs::write(&path, content).unwrap();
+        path
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}
+
+/// Running instance of the given owner.
+pub fn instance(name: &str, api_key: &str, port: u16) -> InstanceInfo {
+    InstanceInfo {
+        container_id: format!("container-{name}"),
+        proxied_port: port,
+        name: name.to_string(),
+        api_key: api_key.to_string(),
+        created_at: now_timestamp(),
+        last_seen: None,
+        image: "arkproject/katana:test".to_string(),
+        launch_args: vec!["katana".to_string()],
+        metrics_port: None,
+        state: InstanceState::Running,
+        tags: BTreeMap::new(),
+        startup_log: None,
+        persist_dir: None,
+        oneshot: false,
+        instance_token: None,
+        fork_rpc_url: None,
+        ci_system: None,
+        ci_run_id: None,
+        external_host: None,
+    }
+}
+
+/// Running external instance of the given owner, served at `addr`.
+pub fn external_instance(name: &str, api_key: &str, addr: SocketAddr) -> InstanceInfo {
+    InstanceInfo {
+        container_id: EXTERNAL_CONTAINER_ID.to_string(),
+        external_host: Some(addr.ip().to_string()),
+        ..instance(name, api_key, addr.port())
+    }
+}
+
+/// User of the given api-key, added to the database.
+pub async fn user(db: &mut SqlxDb, name: &str, api_key: &str) -> AuthenticatedUser {
+    db.user_add(name, Some(api_key.to_string())).await.unwrap();
+
+    AuthenticatedUser {
+        api_key: api_key.to_string(),
+        name: name.to_string(),
+        max_instances: None,
+        is_admin: false,
+        allowed_options: None,
+    }
+}
+
+/// Copy of an authenticated user, for another request.
+pub fn same_user(user: &AuthenticatedUser) -> AuthenticatedUser {
+    AuthenticatedUser {
+        api_key: user.api_key.clone(),
+        name: user.name.clone(),
+        max_instances: user.max_instances,
+        is_admin: user.is_admin,
+        allowed_options: user.allowed_options.clone(),
+    }
+}
+
+/// State over an in-memory database, with the default configuration.
+/// Docker is only reached when used.
+pub async fn state() -> AppState {
+    std::env::set_var("KATANA_CI_IMAGE", "arkproject/katana:test");
+    let config = Arc::new(Config::from_env());
+
+    AppState {
+        db: db().await,
+        docker: DockerManager::new(&config.image),
+        http: HttpClient::new(),
+        config: config.clone(),
+        instance_tasks: Default::default(),
+        ports: PortPool::new(config.port_pool_size),
+        warm_pool: WarmPool::new(config.warm_pool_size),
+        traffic: Default::default(),
+        draining: Default::default(),
+    }
+}
+
+/// State talking to the given mock docker daemon.
+pub async fn docker_state(docker: &MockDocker) -> AppState {
+    let mut state = state().await;
+    state.docker = docker.manager();
+    state
+}
+
+/// JSON body of a response.
+pub async fn json_body(resp: axum::response::Response) -> serde_json::Value {
+    let bytes = hyper::body::to_bytes(resp.into_body()).await.unwrap();
+    serde_json::from_slice(&bytes).unwrap()
+}
+
+/// Changes the configuration of the state.
+pub fn configure(state: &mut AppState, f: impl FnOnce(&mut Config)) {
+    let mut config = (*state.config).clone();
+    f(&mut config);
+    state.config = Arc::new(config);
+}
+
+/// Serves HTTP on a free local port with the given handler, like a Katana instance.
+pub async fn upstream<F, Fut>(handler: F) -> SocketAddr
+where
+    F: Fn(Request<Body>) -> Fut + Clone + Send + Sync + 'static,
+    Fut: Future<Output = Response<Body>> + Send + 'static,
+{
+    let make_svc = make_service_fn(move |_| {
+        let handler = handler.clone();
+        async move {
+            Ok::<_, Infallible>(service_fn(move |req| {
+                let response = handler(req);
+                async move { Ok::<_, Infallible>(response.await) }
+            }))
+        }
+    });
+
+    let server = hyper::Server::bind(&SocketAddr::from(([127, 0, 0, 1], 0))).serve(make_svc);
+    let addr = server.local_addr();
+    tokio::spawn(server);
+    addr
+}
+
+/// Katana instance answering the JSON-RPC calls with a block number
+/// of 1 and a chain id of `KATANA`.
+pub async fn katana() -> SocketAddr {
+    upstream(katana_answer).await
+}
+
+/// Serves the Katana answers on the given local port, failing
+/// if the port is already bound.
+fn katana_on(port: u16) -> Result<(), hyper::Error> {
+    let make_svc = make_service_fn(|_| async {
+        Ok::<_, Infallible>(service_fn(|req| async {
+            Ok::<_, Infallible>(katana_answer(req).await)
+        }))
+    });
+
+    let server = hyper::Server::try_bind(&SocketAddr::from(([127, 0, 0, 1], port)))?;
+    tokio::spawn(server.serve(make_svc));
+    Ok(())
+}
+
+async fn katana_answer(req: Request<Body>) -> Response<Body> {
+    let body = hyper::body::to_bytes(req.into_body()).await.unwrap();
+    let call: serde_json::Value = serde_json::from_slice(&body).unwrap_or_default();
+
+    let result = match call["method"].as_str() {
+        Some("starknet_blockNumber") => json!(1),
+        Some("starknet_blockHashAndNumber") => {
+            json!({ "block_number": 1, "block_hash": "0x1" })
+        }
+        Some("starknet_chainId") => json!("0x4b4154414e41"),
+        _ => json!(null),
+    };
+
+    json_response(
+        StatusCode::OK,
+        json!({ "jsonrpc": "2.0", "id": call["id"], "result": result }),
+    )
+}
+
+/// Local port refusing the connections.
+pub fn closed_port() -> u16 {
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    listener.local_addr().unwrap().port()
+}
+
+/// Call received by the mock docker daemon.
+#[derive(Debug, Clone)]
+pub struct DockerCall {
+    pub method: Method,
+    /// Path and query of the call.
+    pub path: String,
+    pub headers: hyper::HeaderMap,
+    pub body: Bytes,
+}
+
+impl DockerCall {
+    pub fn json(&self) -> serde_json::Value {
+        serde_json::from_slice(&self.body).unwrap()
+    }
+}
+
+/// Container of the mock docker daemon.
+#[derive(Debug, Clone)]
+pub struct MockContainer {
+    /// Docker status, like `created`, `running` or `exited`.
+    pub status: String,
+    pub ip: String,
+    pub labels: HashMap<String, String>,
+    /// Unix timestamp of the creation.
+    pub created: i64,
+    /// Host port published by the container, if any.
+    pub host_port: Option<u16>,
+}
+
+/// Containers, images and recorded calls of the mock docker daemon.
+#[derive(Default)]
+pub struct MockDockerState {
+    pub calls: Vec<DockerCall>,
+    pub containers: BTreeMap<String, MockContainer>,
+    /// Images present locally, the others being pulled.
+    pub images: Vec<String>,
+    /// Progress events streamed by an image pull.
+    pub pull_events: Vec<serde_json::Value>,
+    /// Output of the container logs.
+    pub logs: String,
+    /// Status of every call, like a failing daemon, when set.
+    pub failure: Option<StatusCode>,
+    /// Time taken by a container start.
+    pub start_delay: Duration,
+    /// Number of created containers.
+    pub created: usize,
+    /// Time taken by every call.
+    pub call_delay: Duration,
+    /// Calls being answered, and the most answered at once.
+    pub in_flight: usize,
+    pub max_in_flight: usize,
+    /// Started containers serve the Katana answers on their
+    /// published host port, a port bound twice failing the start.
+    pub serve_started: bool,
+}
+
+/// Docker daemon answering the calls of the manager over HTTP.
+#[derive(Clone)]
+pub struct MockDocker {
+    pub url: String,
+    pub state: Arc<Mutex<MockDockerState>>,
+}
+
+impl MockDocker {
+    pub async fn start() -> Self {
+        let state = Arc::new(Mutex::new(MockDockerState::default()));
+
+        let service_state = state.clone();
+        let make_svc = make_service_fn(move |_| {
+            let state = service_state.clone();
+            async move {
+                Ok::<_, Infallible>(service_fn(move |req| {
+                    let state = state.clone();
+                    async move { Ok::<_, Infallible>(docker_response(&state, req).await) }
+                }))
+            }
+        });
+
+        let server = hyper::Server::bind(&SocketAddr::from(([127, 0, 0, 1], 0))).serve(make_svc);
+        let url = format!("http://{}", server.local_addr());
+        tokio::spawn(server);
+
+        Self { url, state }
+    }
+
+    /// Manager of the default image, talking to this daemon.
+    pub fn manager(&self) -> DockerManager {
+        DockerManager::new("arkproject/katana:test").with_docker_host(&self.url)
+    }
+
+    /// Adds a container in the given status.
+    pub fn add_container(&self, id: &str, status: &str, labels: &[(&str, &str)]) {
+        self.state.lock().unwrap().containers.insert(
+            id.to_string(),
+            MockContainer {
+                status: status.to_string(),
+                ip: "172.17.0.2".to_string(),
+                labels: labels
+                    .iter()
+                    .map(|(k, v)| (k.to_string(), v.to_string()))
+                    .collect(),
+                created: now_timestamp(),
+                host_port: None,
+            },
+        );
+    }
+
+    /// Recorded calls of the given method with a path starting with `prefix`.
+    pub fn calls(&self, method: Method, prefix: &str) -> Vec<DockerCall> {
+        self.state
+            .lock()
+            .unwrap()
+            .calls
+            .iter()
+            .filter(|c| c.method == method && c.path.starts_with(prefix))
+            .cloned()
+            .collect()
+    }
+
+    /// Body of the latest container creation.
+    pub fn created_options(&self) -> serde_json::Value {
+        self.calls(Method::POST, "/containers/create")
+            .last()
+            .expect("no container created")
+            .json()
+    }
+}
+
+/// Answers a call after the call delay, counting the calls answered at once.
+async fn docker_response(
+    shared: &Arc<Mutex<MockDockerState>>,
+    req: Request<Body>,
+) -> Response<Body> {
+    let delay = {
+        let mut state = shared.lock().unwrap();
+        state.in_flight += 1;
+        state.max_in_flight = state.max_in_flight.max(state.in_flight);
+        state.call_delay
+    };
+    tokio::time::sleep(delay).await;
+
+    let resp = docker_answer(shared, req).await;
+    shared.lock().unwrap().in_flight -= 1;
+    resp
+}
+
+async fn docker_answer(shared: &Arc<Mutex<MockDockerState>>, req: Request<Body>) -> Response<Body> {
+    let (parts, body) = req.into_parts();
+    let body = hyper::body::to_bytes(body).await.unwrap_or_default();
+    let path = parts.uri.path().to_string();
+
+    let start_delay = {
+        let mut state = shared.lock().unwrap();
+        state.calls.push(DockerCall {
+            method: parts.method.clone(),
+            path: parts
+                .uri
+                .path_and_query()
+                .map(|p| p.to_string())
+                .unwrap_or_default(),
+            headers: parts.headers.clone(),
+            body: body.clone(),
+        });
+        if let Some(status) = state.failure {
+            return json_response(status, json!({ "message": "mock failure" }));
+        }
+        state.start_delay
+    };
+
+    let segments: Vec<&str> = path.trim_start_matches('/').split('/').collect();
+    if parts.method == Method::POST && segments.last() == Some(&"start") {
+        tokio::time::sleep(start_delay).await;
+    }
+
+    let mut state = shared.lock().unwrap();
+    match (parts.method, segments.as_slice()) {
+        (Method::GET, ["_ping"]) => Response::new(Body::from("OK")),
+        (Method::POST, ["containers", "create"]) => {
+            state.created += 1;
+            let id = format!("mock-{}", state.created);
+            let options: serde_json::Value = serde_json::from_slice(&body).unwrap_or_default();
+            let labels = serde_json::from_value(options["Labels"].clone()).unwrap_or_default();
+            let host_port = options["HostConfig"]["PortBindings"]
+                .as_object()
+                .and_then(|bindings| bindings.values().next())
+                .and_then(|binding| binding[0]["HostPort"].as_str())
+                .and_then(|port| port.parse().ok());
+            state.containers.insert(
+                id.clone(),
+                MockContainer {
+                    status: "created".to_string(),
+                    ip: "172.17.0.2".to_string(),
+                    labels,
+                    created: now_timestamp(),
+                    host_port,
+                },
+            );
+            json_response(StatusCode::CREATED, json!({ "Id": id, "Warnings": [] }))
+        }
+        (Method::GET, ["containers", "json"]) => {
+            let containers: Vec<_> = state
+                .containers
+                .iter()
+                .map(|(id, c)| {
+                    json!({
+                        "Created": c.created,
+                        "Command": "katana",
+                        "Id": id,
+                        "Image": "arkproject/katana:test",
+                        "ImageID": "sha256:0",
+                        "Labels": c.labels,
+                        "Names": [format!("/{id}")],
+                        "Ports": [],
+                        "State": c.status,
+                        "Status": c.status,
+                    })
+                })
+                .collect();
+            json_response(StatusCode::OK, json!(containers))
+        }
+        (method, ["containers", id, action @ ..]) => {
+            let serve_started = state.serve_started;
+            let container = match state.containers.get_mut(*id) {
+                Some(container) => container,
+                None => {
+                    return json_response(
+                        StatusCode::NOT_FOUND,
+                        json!({ "message": format!("No such container: {id}") }),
+                    )
+                }
+            };
+
+            match (method, action) {
+                (Method::POST, ["start"]) => {
+                    if let (true, Some(port)) = (serve_started, container.host_port) {
+                        if katana_on(port).is_err() {
+                            return json_response(
+                                StatusCode::INTERNAL_SERVER_ERROR,
+                                json!({ "message": format!("port {port} is already allocated") }),
+                            );
+                        }
+                    }
+                    container.status = "running".to_string();
+                    empty_response(StatusCode::NO_CONTENT)
+                }
+                (Method::POST, ["stop"]) => {
+                    container.status = "exited".to_string();
+                    empty_response(StatusCode::NO_CONTENT)
+                }
+                (Method::GET, ["json"]) => {
+                    let details = container_details(id, container);
+                    json_response(StatusCode::OK, details)
+                }
+                (Method::GET, ["logs"]) if follows(&parts.uri) => {
+                    followed_logs(shared.clone(), id, log_frames(&state.logs))
+                }
+                (Method::GET, ["logs"]) => Response::new(Body::from(log_frames(&state.logs))),
+                (Method::DELETE, []) => {
+                    state.containers.remove(*id);
+                    empty_response(StatusCode::NO_CONTENT)
+                }
+                _ => empty_response(StatusCode::NOT_FOUND),
+            }
+        }
+        (Method::GET, ["images", ..]) => {
+            let image = path
+                .trim_start_matches("/images/")
+                .trim_end_matches("/json")
+                .to_string();
+            if !state.images.contains(&image) {
+                return json_response(
+                    StatusCode::NOT_FOUND,
+                    json!({ "message": format!("No such image: {image}") }),
+                );
+            }
+            json_response(StatusCode::OK, image_details(&image))
+        }
+        (Method::POST, ["images", "create"]) => {
+            let image =
+                url::form_urlencoded::parse(parts.uri.query().unwrap_or_default().as_bytes())
+                    .find(|(k, _)| k == "fromImage")
+                    .map(|(_, v)| v.to_string())
+                    .unwrap_or_default();
+            state.images.push(image);
+            let events: String = state.pull_events.iter().map(|e| format!("{e}\n")).collect();
+            Response::new(Body::from(events))
+        }
+        _ => empty_response(StatusCode::NOT_FOUND),
+    }
+}
+
+fn json_response(status: StatusCode, body: serde_json::Value) -> Response<Body> {
+    Response::builder()
+        .status(status)
+        .header("content-type", "application/json")
+        .body(Body::from(body.to_string()))
+        .unwrap()
+}
+
+fn empty_response(status: StatusCode) -> Response<Body> {
+    Response::builder()
+        .status(status)
+        .body(Body::empty())
+        .unwrap()
+}
+
+/// Logs multiplexed as docker does, in a single stdout frame.
+fn log_frames(logs: &str) -> Vec<u8> {
+    let mut frame = vec![1, 0, 0, 0];
+    frame.extend((logs.len() as u32).to_be_bytes());
+    frame.extend(logs.as_bytes());
+    frame
+}
+
+fn follows(uri: &hyper::Uri) -> bool {
+    url::form_urlencoded::parse(uri.query().unwrap_or_default().as_bytes())
+        .any(|(k, v)| k == "follow" && v == "true")
+}
+
+/// Followed logs, the stream staying open while the container runs, as docker does.
+fn followed_logs(shared: Arc<Mutex<MockDockerState>>, id: &str, frames: Vec<u8>) -> Response<Body> {
+    let (mut sender, body) = Body::channel();
+    let id = id.to_string();
+    tokio::spawn(async move {
+        if sender.send_data(frames.into()).await.is_err() {
+            return;
+        }
+        loop {
+            let running = shared
+                .lock()
+                .unwrap()
+                .containers
+                .get(&id)
+                .is_some_and(|c| c.status == "running");
+            if !running {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    });
+    Response::new(body)
+}
+
+const MOCK_DATE: &str = "2024-01-01T00:00:00Z";
+
+fn container_config(labels: &HashMap<String, String>) -> serde_json::Value {
+    json!({
+        "AttachStderr": false,
+        "AttachStdin": false,
+        "AttachStdout": false,
+        "Domainname": "",
+        "Hostname": "",
+        "Image": "arkproject/katana:test",
+        "Labels": labels,
+        "OpenStdin": false,
+        "StdinOnce": false,
+        "Tty": false,
+        "User": "",
+        "WorkingDir": "",
+    })
+}
+
+fn container_details(id: &str, container: &MockContainer) -> serde_json::Value {
+    let running = container.status == "running";
+    json!({
+        "AppArmorProfile": "",
+        "Args": [],
+        "Config": container_config(&container.labels),
+        "Created": MOCK_DATE,
+        "Driver": "overlay2",
+        "HostConfig": {
+            "ContainerIDFile": "",
+            "NetworkMode": "default",
+            "Privileged": false,
+            "PublishAllPorts": false,
+        },
+        "HostnamePath": "",
+        "HostsPath": "",
+        "LogPath": "",
+        "Id": id,
+        "Image": "arkproject/katana:test",
+        "MountLabel": "",
+        "Name": format!("/{id}"),
+        "NetworkSettings": {
+            "Bridge": "",
+            "Gateway": "",
+            "IPAddress": container.ip,
+            "IPPrefixLen": 16,
+            "MacAddress": "",
+            "Networks": {},
+        },
+        "Path": "katana",
+        "ProcessLabel": "",
+        "ResolvConfPath": "",
+        "RestartCount": 0,
+        "State": {
+            "Error": "",
+            "ExitCode": if container.status == "exited" { 1 } else { 0 },
+            "FinishedAt": MOCK_DATE,
+            "OOMKilled": false,
+            "Paused": false,
+            "Pid": 1,
+            "Restarting": false,
+            "Running": running,
+            "StartedAt": MOCK_DATE,
+            "Status": container.status,
+        },
+        "Mounts": [],
+    })
+}
+
+fn image_details(image: &str) -> serde_json::Value {
+    json!({
+        "Architecture": "amd64",
+        "Author": "",
+        "Comment": "",
+        "Config": container_config(&HashMap::new()),
+        "Created": MOCK_DATE,
+        "DockerVersion": "",
+        "Id": "sha256:0",
+        "Os": "linux",
+        "Parent": "",
+        "RepoTags": [image],
+        "RepoDigests": [],
+        "Size": 0,
+        "VirtualSize": 0,
+    })
+}
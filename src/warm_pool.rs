@@ -0,0 +1,195 @@
+//! Pool of Katana containers started ahead of time with the default
+//! options, for the starts without any other option to skip the
+//! container creation and readiness wait.
+//!
+//! The pool containers are labeled with their port, to be re-adopted
+//! after a restart.
+use axum::extract::FromRef;
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::Notify;
+use tokio::task::JoinHandle;
+use tracing::{info, trace, warn};
+
+use crate::db::SqlxDb;
+use crate::docker_manager::KatanaDockerOptions;
+use crate::handlers;
+use crate::AppState;
+
+/// Delay before filling the pool again after a failure.
+const RETRY_DELAY: Duration = Duration::from_secs(5);
+
+/// Started container of the pool, not claimed by any instance yet.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PooledContainer {
+    pub id: String,
+    pub port: u16,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct WarmPool {
+    size: usize,
+    containers: Arc<Mutex<VecDeque<PooledContainer>>>,
+    consumed: Arc<Notify>,
+}
+
+impl WarmPool {
+    pub fn new(size: usize) -> Self {
+        Self {
+            size,
+            ..Default::default()
+        }
+    }
+
+    /// Takes the oldest container of the pool, if any.
+    pub fn take(&self) -> Option<PooledContainer> {
+        let container = self.containers.lock().unwrap().pop_front();
+        if container.is_some() {
+            self.consumed.notify_one();
+        }
+        container
+    }
+
+    /// Adds a started container, returning false if the pool is full.
+    pub fn adopt(&self, container: PooledContainer) -> bool {
+        let mut containers = self.containers.lock().unwrap();
+        if containers.len() >= self.size {
+            return false;
+        }
+
+        trace!(
+            "container {} of port {} pooled",
+            container.id,
+            container.port
+        );
+        containers.push_back(container);
+        true
+    }
+
+    pub fn contains(&self, container_id: &str) -> bool {
+        self.containers
+            .lock()
+            .unwrap()
+            .iter()
+            .any(|c| c.id == container_id)
+    }
+
+    /// Fills the pool, then refills it each time a container is taken.
+    pub fn spawn_replenish(&self, state: AppState) -> JoinHandle<()> {
+        info!("🔥 warm pool of {} containers enabled", self.size);
+
+        let pool = self.clone();
+        tokio::spawn(async move {
+            loop {
+                if pool.replenish(&state).await {
+                    pool.consumed.notified().await;
+                } else {
+                    tokio::time::sleep(RETRY_DELAY).await;
+                }
+            }
+        })
+    }
+
+    /// Starts containers until the pool is full, returning false on a failure.
+    async fn replenish(&self, state: &AppState) -> bool {
+        let db = SqlxDb::from_ref(state);
+
+        while self.containers.lock().unwrap().len() < self.size {
+            let port = match state.ports.take(&db).await {
+                Some(port) => port,
+                None => return false,
+            };
+
+            let opts = KatanaDockerOptions {
+                port: port as u32,
+                pooled: true,
+                ..Default::default()
+            };
+            let id = match handlers::launch_container(state, &opts).await {
+                Ok(id) => id,
+                Err((_, e)) => {
+                    warn!("can't start a warm pool container: {e}");
+                    return false;
+                }
+            };
+
+            // Refilled in the meantime with a container not claimed after all.
+            if !self.adopt(PooledContainer {
+                id: id.clone(),
+                port,
+            }) {
+                let force = true;
+                if let Err(e) = state.docker.remove(&id, force).await {
+                    warn!("can't remove the extra warm pool container {id}: {e}");
+                }
+            }
+        }
+
+        trace!("warm pool replenished");
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{self, MockDocker};
+    use hyper::Method;
+
+    async fn wait_pooled(pool: &WarmPool, n: usize) {
+        let filled = async {
+            while pool.containers.lock().unwrap().len() < n {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        };
+        tokio::time::timeout(Duration::from_secs(5), filled)
+            .await
+            .expect("warm pool not replenished");
+    }
+
+    #[tokio::test]
+    async fn pool_is_filled_with_labeled_containers_and_refilled_once_taken() {
+        let docker = MockDocker::start().await;
+        docker.state.lock().unwrap().serve_started = true;
+        let mut state = test_utils::docker_state(&docker).await;
+        state.warm_pool = WarmPool::new(1);
+        let replenish = state.warm_pool.spawn_replenish(state.clone());
+
+        wait_pooled(&state.warm_pool, 1).await;
+        let labels = docker.created_options()["Labels"].clone();
+        let pooled = state.warm_pool.take().unwrap();
+        assert_eq!(labels["katana-ci.pool"], pooled.port.to_string());
+
+        wait_pooled(&state.warm_pool, 1).await;
+        assert_ne!(state.warm_pool.take(), Some(pooled));
+        replenish.abort();
+    }
+
+    #[tokio::test]
+    async fn container_started_into_a_refilled_pool_is_removed() {
+        let docker = MockDocker::start().await;
+        {
+            let mut docker = docker.state.lock().unwrap();
+            docker.serve_started = true;
+            docker.start_delay = Duration::from_millis(200);
+        }
+        let mut state = test_utils::docker_state(&docker).await;
+        state.warm_pool = WarmPool::new(1);
+        let released = PooledContainer {
+            id: "released".to_string(),
+            port: 10500,
+        };
+
+        let refilled = async {
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            assert!(state.warm_pool.adopt(released.clone()));
+        };
+        let (replenished, _) = tokio::join!(state.warm_pool.replenish(&state), refilled);
+
+        assert!(replenished);
+        assert_eq!(state.warm_pool.take(), Some(released));
+        assert_eq!(docker.calls(Method::POST, "/containers/create").len(), 1);
+        assert!(docker.state.lock().unwrap().containers.is_empty());
+    }
+}